@@ -1,72 +1,57 @@
-// Find the world name in the world WIT file
+// Find the world the caller-utils bindings are generated from, which imports every interface
 fn find_world_name(api_dir: &Path) -> Result<String> {
     // Look for world definition files
     for entry in WalkDir::new(api_dir)
         .max_depth(1)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
     {
         let path = entry.path();
         
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "wit") {
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "wit") {
             if let Ok(content) = fs::read_to_string(path) {
-                if content.contains("world ") {
-                    println!("Analyzing world definition file: {}", path.display());
-                    
-                    // Extract the world name
-                    let lines: Vec<&str> = content.lines().collect();
-                    
-                    if let Some(world_line) = lines.iter().find(|line| line.trim().starts_with("world ")) {
-                        println!("World line: {}", world_line);
-                        
-                        if let Some(world_name) = world_line.trim().split_whitespace().nth(1) {
-                            let clean_name = world_name.trim_end_matches(" {");
-                            println!("Extracted world name: {}", clean_name);
-                            return Ok(clean_name.to_string());
-                        }
-                    }
+                if parse_worlds(&content).iter().any(|world| world.name == CALLER_UTILS_WORLD) {
+                    println!("Found world {} in {}", CALLER_UTILS_WORLD, path.display());
+                    return Ok(CALLER_UTILS_WORLD.to_string());
                 }
             }
         }
     }
     
     // If no world name is found, we should fail
-    bail!("No world name found in any WIT file. Cannot generate caller-utils without a world name.")
+    bail!("No world {} found in any WIT file. Cannot generate caller-utils without it.", CALLER_UTILS_WORLD)
 }
 
 use anyhow::{Context, Result, bail};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use toml::Value;
 use walkdir::WalkDir;
 
-// Convert kebab-case to snake_case
-pub fn to_snake_case(s: &str) -> String {
-    s.replace('-', "_")
-}
+use crate::config::Config;
+use crate::generated_header::{ensure_unmodified, input_hash, with_header};
+use crate::manifest::Manifest;
+use crate::naming::{method_variant_name, to_pascal_case, to_rust_identifier, to_snake_case};
+use crate::wit_generator::{
+    CALLER_UTILS_WORLD, DEPRECATED_DOC, FEATURE_COMMENT, JSON_STRING_SERDE, MAPPING_COMMENT, RUST_NAME_COMMENT,
+    SERDE_COMMENT, SHARED_TYPES_INTERFACE,
+};
+use crate::world_file::parse_worlds;
 
-// Convert kebab-case to PascalCase
-pub fn to_pascal_case(s: &str) -> String {
-    let parts = s.split('-');
-    let mut result = String::new();
-    
-    for part in parts {
-        if !part.is_empty() {
-            let mut chars = part.chars();
-            if let Some(first_char) = chars.next() {
-                result.push(first_char.to_uppercase().next().unwrap());
-                result.extend(chars);
-            }
-        }
+// Convert WIT type to Rust type
+fn wit_type_to_rust(wit_type: &str, config: &Config) -> String {
+    // Mapped types go back to the Rust type configured for callers
+    if let Some(mapping) = config.type_mappings.iter().find(|m| m.wit_name == wit_type) {
+        return mapping.caller_type.clone();
     }
-    
-    result
-}
 
-// Convert WIT type to Rust type
-fn wit_type_to_rust(wit_type: &str) -> String {
     match wit_type {
+        "s8" => "i8".to_string(),
+        "u8" => "u8".to_string(),
+        "s16" => "i16".to_string(),
+        "u16" => "u16".to_string(),
         "s32" => "i32".to_string(),
         "u32" => "u32".to_string(),
         "s64" => "i64".to_string(),
@@ -75,21 +60,27 @@ fn wit_type_to_rust(wit_type: &str) -> String {
         "f64" => "f64".to_string(),
         "string" => "String".to_string(),
         "bool" => "bool".to_string(),
-        "unit" => "()".to_string(),
-        "address" => "WitAddress".to_string(),
+        "char" => "char".to_string(),
+        "tuple<>" => "()".to_string(),
+        // Types from the `standard` interface map back to their hyperware_process_lib types
+        "address" => "Address".to_string(),
+        "process-id" => "ProcessId".to_string(),
+        "package-id" => "PackageId".to_string(),
+        "capability" => "Capability".to_string(),
+        "lazy-load-blob" => "LazyLoadBlob".to_string(),
         t if t.starts_with("list<") => {
             let inner_type = &t[5..t.len() - 1];
-            format!("Vec<{}>", wit_type_to_rust(inner_type))
+            format!("Vec<{}>", wit_type_to_rust(inner_type, config))
         },
         t if t.starts_with("option<") => {
             let inner_type = &t[7..t.len() - 1];
-            format!("Option<{}>", wit_type_to_rust(inner_type))
+            format!("Option<{}>", wit_type_to_rust(inner_type, config))
         },
         t if t.starts_with("tuple<") => {
             let inner_types = &t[6..t.len() - 1];
             let rust_types: Vec<String> = inner_types
                 .split(", ")
-                .map(|t| wit_type_to_rust(t))
+                .map(|t| wit_type_to_rust(t, config))
                 .collect();
             format!("({})", rust_types.join(", "))
         },
@@ -99,12 +90,29 @@ fn wit_type_to_rust(wit_type: &str) -> String {
 }
 
 // Generate default value for Rust type
-fn generate_default_value(rust_type: &str) -> String {
+fn generate_default_value(rust_type: &str, type_defs: &[WitTypeDef], config: &Config) -> String {
+    // Mapped types may not implement Default
+    let mapping = config.type_mappings.iter().find(|m| m.caller_type == rust_type);
+    if let Some(default_value) = mapping.and_then(|m| m.default_value.as_ref()) {
+        return default_value.clone();
+    }
+
+    // WIT enums and flags don't implement Default in the generated bindings
+    let custom_def = type_defs.iter().find(|def| to_pascal_case(&def.name) == rust_type);
+    match custom_def.map(|def| &def.kind) {
+        Some(WitTypeKind::Enum { first_case }) => {
+            return format!("{}::{}", rust_type, to_pascal_case(first_case));
+        }
+        Some(WitTypeKind::Flags) => return format!("{}::empty()", rust_type),
+        _ => {}
+    }
+
     match rust_type {
-        "i32" | "u32" | "i64" | "u64" => "0".to_string(),
+        "i8" | "u8" | "i16" | "u16" | "i32" | "u32" | "i64" | "u64" => "0".to_string(),
         "f32" | "f64" => "0.0".to_string(),
         "String" => "String::new()".to_string(),
         "bool" => "false".to_string(),
+        "char" => "'\\0'".to_string(),
         "()" => "()".to_string(),
         t if t.starts_with("Vec<") => "Vec::new()".to_string(),
         t if t.starts_with("Option<") => "None".to_string(),
@@ -112,7 +120,7 @@ fn generate_default_value(rust_type: &str) -> String {
             let inner_part = t.trim_start_matches('(').trim_end_matches(')');
             let parts: Vec<_> = inner_part.split(", ").collect();
             let default_values: Vec<_> = parts.iter()
-                .map(|part| generate_default_value(part))
+                .map(|part| generate_default_value(part, type_defs, config))
                 .collect();
             format!("({})", default_values.join(", "))
         },
@@ -121,6 +129,216 @@ fn generate_default_value(rust_type: &str) -> String {
     }
 }
 
+// Kind of a WIT type definition found in an interface file
+#[derive(Clone)]
+enum WitTypeKind {
+    Record,
+    Variant,
+    Enum { first_case: String },
+    Flags,
+    Alias,
+}
+
+// A WIT item gated with `@deprecated`
+#[derive(Clone)]
+struct Deprecated {
+    // Version from the `@deprecated` gate
+    since: String,
+    // Note from the `/// Deprecated: ...` doc line
+    note: Option<String>,
+}
+
+impl Deprecated {
+    // The `#[deprecated]` attribute line for the Rust item generated for it
+    fn attribute(&self) -> String {
+        match &self.note {
+            Some(note) => format!("#[deprecated(since = {:?}, note = {:?})]\n", self.since, note),
+            None => format!("#[deprecated(since = {:?})]\n", self.since),
+        }
+    }
+}
+
+// A field of a record or a case of a variant or enum
+#[derive(Clone)]
+struct WitMember {
+    name: String,
+    // Type of a field or payload of a case, if it has one
+    wit_type: Option<String>,
+    // Arguments of the #[serde(...)] attribute recorded for it, e.g. `rename = "userName"`
+    serde: Option<String>,
+    // Documentation, from the `///` lines above it
+    docs: Vec<String>,
+}
+
+// Structure to represent a WIT type definition
+#[derive(Clone)]
+struct WitTypeDef {
+    name: String,
+    kind: WitTypeKind,
+    deprecated: Option<Deprecated>,
+    // Documentation, from the `///` lines above the definition
+    docs: Vec<String>,
+    // Fields of a record, or cases of a variant or enum
+    members: Vec<WitMember>,
+    // Type an alias stands for
+    aliased: Option<String>,
+    // Rust path of the type mapping it was generated from, if any
+    mapped_from: Option<String>,
+}
+
+impl WitTypeDef {
+    fn new(name: &str, kind: WitTypeKind, deprecated: Option<Deprecated>, docs: Vec<String>) -> Self {
+        WitTypeDef {
+            name: name.to_string(),
+            kind,
+            deprecated,
+            docs,
+            members: Vec::new(),
+            aliased: None,
+            mapped_from: None,
+        }
+    }
+
+    // Names of the WIT types its members or aliased type refer to
+    fn references(&self) -> Vec<&str> {
+        self.members
+            .iter()
+            .filter_map(|member| member.wit_type.as_deref())
+            .chain(self.aliased.as_deref())
+            .flat_map(|ty| ty.split(|c: char| !c.is_ascii_alphanumeric() && c != '-'))
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+}
+
+// Strip keyword escapes like `%type` from the identifiers of a WIT line. Comments and docs are
+// kept as written.
+fn unescape_identifiers(line: &str) -> String {
+    if line.starts_with("//") {
+        return line.to_string();
+    }
+    line.replace('%', "")
+}
+
+// Parse the members of the record, variant or enum opened at `lines[*i]`, leaving `*i` at its
+// closing brace
+fn parse_members(lines: &[&str], i: &mut usize) -> Vec<WitMember> {
+    let mut members = Vec::new();
+    if lines[*i].trim().ends_with("{}") {
+        return members;
+    }
+    let mut serde = None;
+    let mut docs = Vec::new();
+    *i += 1;
+    while *i < lines.len() && !lines[*i].trim().starts_with('}') {
+        let line = unescape_identifiers(lines[*i].trim());
+        let line = line.as_str();
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.strip_prefix(' ').unwrap_or(doc).to_string());
+        } else if let Some(args) = line.strip_prefix("// ").and_then(|l| l.strip_prefix(SERDE_COMMENT)) {
+            serde = Some(args.to_string());
+        } else if !line.is_empty() && !line.starts_with("//") && !line.starts_with('@') {
+            let line = line.trim_end_matches(',');
+            let (name, wit_type) = match line.split_once(':') {
+                Some((name, ty)) => (name, Some(ty.trim().to_string())),
+                None => match line.split_once('(') {
+                    Some((name, ty)) => (name, Some(ty.trim_end_matches(')').to_string())),
+                    None => (line, None),
+                },
+            };
+            members.push(WitMember {
+                name: name.trim().to_string(),
+                wit_type,
+                serde: serde.take(),
+                docs: std::mem::take(&mut docs),
+            });
+        }
+        *i += 1;
+    }
+    members
+}
+
+// Serde adapter for fields the WIT encodes as a JSON string: callers hold the JSON of the value,
+// while the processes send the value itself
+const JSON_STRING_MODULE: &str = r#"mod json_string {
+    pub fn serialize<S: serde::Serializer>(json: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        let value: serde_json::Value = serde_json::from_str(json).map_err(serde::ser::Error::custom)?;
+        serde::Serialize::serialize(&value, serializer)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        let value: serde_json::Value = serde::Deserialize::deserialize(deserializer)?;
+        Ok(value.to_string())
+    }
+}
+"#;
+
+// `///` lines for documentation parsed from the WIT, indented by `indent`
+fn doc_comment(docs: &[String], indent: &str) -> String {
+    let mut comment = String::new();
+    for line in docs {
+        if line.is_empty() {
+            comment.push_str(&format!("{}///\n", indent));
+        } else {
+            comment.push_str(&format!("{}/// {}\n", indent, line));
+        }
+    }
+    comment
+}
+
+// Rust definition of a type whose serde names differ from those of the generated bindings,
+// or that contains such a type, carrying the serde attributes recorded in the WIT
+fn local_type_definition(def: &WitTypeDef, config: &Config) -> String {
+    let mut rust = doc_comment(&def.docs, "");
+    rust.push_str(&def.deprecated.as_ref().map(Deprecated::attribute).unwrap_or_default());
+    let name = to_pascal_case(&def.name);
+    let derive = "#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]\n";
+    let member_attrs = |member: &WitMember| {
+        let mut attrs = doc_comment(&member.docs, "    ");
+        if let Some(args) = &member.serde {
+            attrs.push_str(&format!("    #[serde({})]\n", args));
+        }
+        attrs
+    };
+    match def.kind {
+        WitTypeKind::Record => {
+            rust.push_str(derive);
+            rust.push_str(&format!("pub struct {} {{\n", name));
+            for member in &def.members {
+                rust.push_str(&member_attrs(member));
+                rust.push_str(&format!(
+                    "    pub {}: {},\n",
+                    to_rust_identifier(to_snake_case(&member.name)),
+                    wit_type_to_rust(member.wit_type.as_deref().unwrap_or("tuple<>"), config)
+                ));
+            }
+            rust.push('}');
+        }
+        WitTypeKind::Variant | WitTypeKind::Enum { .. } => {
+            rust.push_str(derive);
+            rust.push_str(&format!("pub enum {} {{\n", name));
+            for member in &def.members {
+                rust.push_str(&member_attrs(member));
+                match &member.wit_type {
+                    Some(ty) => rust.push_str(&format!(
+                        "    {}({}),\n",
+                        to_pascal_case(&member.name),
+                        wit_type_to_rust(ty, config)
+                    )),
+                    None => rust.push_str(&format!("    {},\n", to_pascal_case(&member.name))),
+                }
+            }
+            rust.push('}');
+        }
+        // Flags have no members, so only aliases can contain the other types
+        WitTypeKind::Alias | WitTypeKind::Flags => {
+            let aliased = def.aliased.as_deref().unwrap_or("tuple<>");
+            rust.push_str(&format!("pub type {} = {};", name, wit_type_to_rust(aliased, config)));
+        }
+    }
+    rust
+}
+
 // Structure to represent a field in a WIT signature struct
 struct SignatureField {
     name: String,
@@ -130,39 +348,40 @@ struct SignatureField {
 // Structure to represent a WIT signature struct
 struct SignatureStruct {
     function_name: String,
+    // Name of the Rust handler method, if recorded in the WIT file
+    rust_name: Option<String>,
+    // Documentation of the handler, from the `///` lines above the record
+    docs: Vec<String>,
+    deprecated: Option<Deprecated>,
+    // Cargo feature the handler is gated behind, if any
+    feature: Option<String>,
     attr_type: String,
     fields: Vec<SignatureField>,
 }
 
 // Find all interface imports in the world WIT file
-fn find_interfaces_in_world(api_dir: &Path) -> Result<Vec<String>> {
+fn find_interfaces_in_world(api_dir: &Path, world_name: &str) -> Result<Vec<String>> {
     let mut interfaces = Vec::new();
     
     // Find world definition files
     for entry in WalkDir::new(api_dir)
         .max_depth(1)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
     {
         let path = entry.path();
         
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "wit") {
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "wit") {
             if let Ok(content) = fs::read_to_string(path) {
-                if content.contains("world ") {
-                    println!("Analyzing world definition file: {}", path.display());
+                for world in parse_worlds(&content).into_iter().filter(|w| w.name == world_name) {
+                    println!("Analyzing world {} in {}", world.name, path.display());
                     
                     // Extract import statements
-                    for line in content.lines() {
-                        let line = line.trim();
-                        if line.starts_with("import ") && line.ends_with(";") {
-                            let interface = line
-                                .trim_start_matches("import ")
-                                .trim_end_matches(";")
-                                .trim();
-                            
-                            interfaces.push(interface.to_string());
-                            println!("  Found interface import: {}", interface);
-                        }
+                    for (_, interface) in world.imports {
+                        let interface = interface.trim_start_matches('%');
+                        println!("  Found interface import: {}", interface);
+                        interfaces.push(interface.to_string());
                     }
                 }
             }
@@ -173,33 +392,106 @@ fn find_interfaces_in_world(api_dir: &Path) -> Result<Vec<String>> {
 }
 
 // Parse WIT file to extract function signatures and type definitions
-fn parse_wit_file(file_path: &Path) -> Result<(Vec<SignatureStruct>, Vec<String>)> {
+fn parse_wit_file(file_path: &Path) -> Result<(Vec<SignatureStruct>, Vec<WitTypeDef>)> {
     println!("Parsing WIT file: {}", file_path.display());
     
     let content = fs::read_to_string(file_path)
         .with_context(|| format!("Failed to read WIT file: {}", file_path.display()))?;
+
     
     let mut signatures = Vec::new();
-    let mut type_names = Vec::new();
+    let mut type_defs = Vec::new();
     
     // Simple parser for WIT files to extract record definitions and types
     let lines: Vec<_> = content.lines().collect();
     let mut i = 0;
+    // Rust method name recorded in the comment above the next signature struct
+    let mut rust_name = None;
+    // Feature recorded in the comment above the next signature struct
+    let mut feature = None;
+    // Mapped Rust type recorded in the comment above the next type
+    let mut mapped_from = None;
+    // `///` lines and `@deprecated` version above the next item
+    let mut docs = Vec::new();
+    let mut deprecated_version = None;
     
     while i < lines.len() {
-        let line = lines[i].trim();
+        let line = unescape_identifiers(lines[i].trim());
+        let line = line.as_str();
+        let (item_docs, item_deprecated) = if line.starts_with("//") || line.starts_with('@') || line.is_empty() {
+            (Vec::new(), None)
+        } else {
+            let item_docs = std::mem::take(&mut docs);
+            let deprecated = deprecated_version.take().map(|since| Deprecated {
+                since,
+                note: item_docs.iter().find_map(|doc: &String| {
+                    doc.strip_prefix(DEPRECATED_DOC)?.strip_prefix(": ").map(str::to_string)
+                }),
+            });
+            (item_docs, deprecated)
+        };
         
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.strip_prefix(' ').unwrap_or(doc).to_string());
+        }
+        else if let Some(version) = line.strip_prefix("@deprecated(version =").and_then(|l| l.strip_suffix(')')) {
+            deprecated_version = Some(version.trim().to_string());
+        }
+        else if let Some(name) = line.strip_prefix("// ").and_then(|l| l.strip_prefix(RUST_NAME_COMMENT)) {
+            rust_name = Some(name.to_string());
+        }
+        else if let Some(name) = line.strip_prefix("// ").and_then(|l| l.strip_prefix(FEATURE_COMMENT)) {
+            feature = Some(name.to_string());
+        }
+        else if let Some(path) = line.strip_prefix("// ").and_then(|l| l.strip_prefix(MAPPING_COMMENT)) {
+            mapped_from = Some(path.to_string());
+        }
         // Look for record definitions that aren't signature structs
-        if line.starts_with("record ") && !line.contains("-signature-") {
-            let record_name = line.trim_start_matches("record ").trim_end_matches(" {").trim();
+        else if line.starts_with("record ") && !line.contains("-signature-") {
+            // Empty records are written on a single line as `record name {}`
+            let record_name = line.trim_start_matches("record ").trim_end_matches("{}").trim_end_matches(" {").trim();
             println!("  Found type: record {}", record_name);
-            type_names.push(record_name.to_string());
+            let mut def = WitTypeDef::new(record_name, WitTypeKind::Record, item_deprecated, item_docs);
+            def.mapped_from = mapped_from.take();
+            def.members = parse_members(&lines, &mut i);
+            type_defs.push(def);
+        }
+        // Look for type aliases (newtypes and tuple structs)
+        else if line.starts_with("type ") {
+            if let Some(alias_name) = line.trim_start_matches("type ").split('=').next() {
+                let alias_name = alias_name.trim();
+                println!("  Found type: alias {}", alias_name);
+                let mut def = WitTypeDef::new(alias_name, WitTypeKind::Alias, item_deprecated, item_docs);
+                def.mapped_from = mapped_from.take();
+                def.aliased = line.split_once('=').map(|(_, ty)| ty.trim().trim_end_matches(';').to_string());
+                type_defs.push(def);
+            }
         }
         // Look for variant definitions (enums)
         else if line.starts_with("variant ") {
             let variant_name = line.trim_start_matches("variant ").trim_end_matches(" {").trim();
             println!("  Found type: variant {}", variant_name);
-            type_names.push(variant_name.to_string());
+            let mut def = WitTypeDef::new(variant_name, WitTypeKind::Variant, item_deprecated, item_docs);
+            def.members = parse_members(&lines, &mut i);
+            type_defs.push(def);
+        }
+        // Look for enum definitions (fieldless Rust enums)
+        else if line.starts_with("enum ") {
+            let enum_name = line.trim_start_matches("enum ").trim_end_matches(" {").trim();
+            println!("  Found type: enum {}", enum_name);
+
+            // Remember the first case, used when a default value is needed
+            let members = parse_members(&lines, &mut i);
+            let first_case = members.first().map(|case| case.name.clone()).unwrap_or_default();
+            let mut def = WitTypeDef::new(enum_name, WitTypeKind::Enum { first_case }, item_deprecated, item_docs);
+            def.members = members;
+            type_defs.push(def);
+        }
+        // Look for flags definitions (bitflags types)
+        else if line.starts_with("flags ") {
+            let flags_name = line.trim_start_matches("flags ").trim_end_matches(" {").trim();
+            println!("  Found type: flags {}", flags_name);
+            type_defs.push(WitTypeDef::new(flags_name, WitTypeKind::Flags, item_deprecated, item_docs));
         }
         // Look for signature record definitions
         else if line.starts_with("record ") && line.contains("-signature-") {
@@ -222,7 +514,8 @@ fn parse_wit_file(file_path: &Path) -> Result<(Vec<SignatureStruct>, Vec<String>
             i += 1;
             
             while i < lines.len() && !lines[i].trim().starts_with("}") {
-                let field_line = lines[i].trim();
+                let field_line = unescape_identifiers(lines[i].trim());
+                let field_line = field_line.as_str();
                 
                 // Skip comments and empty lines
                 if field_line.starts_with("//") || field_line.is_empty() {
@@ -248,6 +541,10 @@ fn parse_wit_file(file_path: &Path) -> Result<(Vec<SignatureStruct>, Vec<String>
             
             signatures.push(SignatureStruct {
                 function_name,
+                rust_name: rust_name.take(),
+                feature: feature.take(),
+                docs: item_docs,
+                deprecated: item_deprecated,
                 attr_type,
                 fields,
             });
@@ -257,17 +554,41 @@ fn parse_wit_file(file_path: &Path) -> Result<(Vec<SignatureStruct>, Vec<String>
     }
     
     println!("Extracted {} signature structs and {} type definitions from {}", 
-             signatures.len(), type_names.len(), file_path.display());
-    Ok((signatures, type_names))
+             signatures.len(), type_defs.len(), file_path.display());
+    Ok((signatures, type_defs))
+}
+
+// Doc comment and attributes of a stub: the handler's documentation, what the stub is for,
+// whether the handler is deprecated and the feature it is gated behind
+fn stub_attributes(signature: &SignatureStruct) -> String {
+    let mut docs = doc_comment(&signature.docs, "");
+    if !docs.is_empty() {
+        docs.push_str("///\n");
+    }
+    docs.push_str(&format!(
+        "/// Generated stub for `{}` {} RPC call\n",
+        signature.function_name, signature.attr_type
+    ));
+    if let Some(deprecated) = &signature.deprecated {
+        docs.push_str(&deprecated.attribute());
+    }
+    if let Some(feature) = &signature.feature {
+        docs.push_str(&format!("#[cfg(feature = {:?})]\n", feature));
+    }
+    docs
 }
 
 // Generate a Rust async function from a signature struct
-fn generate_async_function(signature: &SignatureStruct) -> String {
+fn generate_async_function(signature: &SignatureStruct, type_defs: &[WitTypeDef], config: &Config) -> String {
     // Convert function name from kebab-case to snake_case
     let snake_function_name = to_snake_case(&signature.function_name);
     
-    // Get pascal case version for the JSON request format
-    let pascal_function_name = to_pascal_case(&signature.function_name);
+    // The JSON request is keyed by the variant the hyperprocess macro derives from the Rust
+    // method name, which can't always be reconstructed from the kebab-case WIT name
+    let pascal_function_name = match &signature.rust_name {
+        Some(rust_name) => method_variant_name(rust_name),
+        None => to_pascal_case(&signature.function_name),
+    };
     
     // Function full name with attribute type
     let full_function_name = format!("{}_{}_rpc", snake_function_name, signature.attr_type);
@@ -279,8 +600,8 @@ fn generate_async_function(signature: &SignatureStruct) -> String {
     let mut target_param = "";
     
     for field in &signature.fields {
-        let field_name_snake = to_snake_case(&field.name);
-        let rust_type = wit_type_to_rust(&field.wit_type);
+        let field_name_snake = to_rust_identifier(to_snake_case(&field.name));
+        let rust_type = wit_type_to_rust(&field.wit_type, config);
         
         if field.name == "target" {
             if field.wit_type == "string" {
@@ -309,7 +630,7 @@ fn generate_async_function(signature: &SignatureStruct) -> String {
     
     // For HTTP endpoints, just return a default implementation for now
     if signature.attr_type == "http" {
-        let default_value = generate_default_value(&return_type);
+        let default_value = generate_default_value(&return_type, type_defs, config);
         
         // Add underscore prefix to all parameters for HTTP stubs
         let all_params_with_underscore = if target_param.is_empty() {
@@ -345,9 +666,8 @@ fn generate_async_function(signature: &SignatureStruct) -> String {
         };
         
         return format!(
-            "/// Generated stub for `{}` {} RPC call\npub async fn {}({}) -> {} {{\n    // TODO: Implement HTTP endpoint\n    SendResult::Success({})\n}}",
-            signature.function_name,
-            signature.attr_type,
+            "{}pub async fn {}({}) -> {} {{\n    // TODO: Implement HTTP endpoint\n    SendResult::Success({})\n}}",
+            stub_attributes(signature),
             full_function_name,
             all_params_with_underscore,
             wrapped_return_type,
@@ -371,9 +691,8 @@ fn generate_async_function(signature: &SignatureStruct) -> String {
     
     // Generate function with implementation using send
     format!(
-        "/// Generated stub for `{}` {} RPC call\npub async fn {}({}) -> {} {{\n    let request = {};\n    send::<{}>(&request, target, 30).await\n}}",
-        signature.function_name,
-        signature.attr_type,
+        "{}pub async fn {}({}) -> {} {{\n    let request = {};\n    send::<{}>(&request, target, 30).await\n}}",
+        stub_attributes(signature),
         full_function_name,
         all_params,
         wrapped_return_type,
@@ -383,67 +702,57 @@ fn generate_async_function(signature: &SignatureStruct) -> String {
 }
 
 // Create the caller-utils crate with a single lib.rs file
-fn create_caller_utils_crate(api_dir: &Path, base_dir: &Path) -> Result<()> {
+fn create_caller_utils_crate(
+    api_dir: &Path,
+    base_dir: &Path,
+    projects: &[PathBuf],
+    config: &Config,
+    manifest: &mut Manifest,
+    force: bool,
+) -> Result<()> {
     // Path to the new crate
     let caller_utils_dir = base_dir.join("caller-utils");
     println!("Creating caller-utils crate at {}", caller_utils_dir.display());
+    let lib_rs_path = caller_utils_dir.join("src").join("lib.rs");
+    ensure_unmodified(&lib_rs_path, force)?;
+    if !caller_utils_dir.exists() {
+        manifest.directories.insert(Manifest::relative(base_dir, &caller_utils_dir));
+    }
     
     // Create directories
     fs::create_dir_all(&caller_utils_dir)?;
     fs::create_dir_all(caller_utils_dir.join("src"))?;
     println!("Created project directory structure");
     
-    // Create Cargo.toml
-    let cargo_toml = r#"[package]
-name = "caller-utils"
-version = "0.1.0"
-edition = "2021"
-publish = false
-
-[dependencies]
-anyhow = "1.0"
-hyperware_process_lib = { version = "1.0.2", features = ["logging"] }
-process_macros = "0.1.0"
-futures-util = "0.3"
-serde = { version = "1.0", features = ["derive"] }
-serde_json = "1.0"
-wit_parser = { path = "../crates/wit_parser" }
-once_cell = "1.20.2"
-hyperware_app_common = { path = "../crates/hyperware_app_common" }
-futures = "0.3"
-uuid = { version = "1.0" }
-
-
-[lib]
-crate-type = ["cdylib", "lib"]
-"#;
-    
-    fs::write(caller_utils_dir.join("Cargo.toml"), cargo_toml)
-        .with_context(|| "Failed to write caller-utils Cargo.toml")?;
-    
-    println!("Created Cargo.toml for caller-utils");
-    
     // Get the world name
     let world_name = find_world_name(api_dir)?;
     
     // Get all interfaces from the world file
-    let interface_imports = find_interfaces_in_world(api_dir)?;
+    let interface_imports = find_interfaces_in_world(api_dir, &world_name)?;
     
     // Store all types from each interface
-    let mut interface_types: HashMap<String, Vec<String>> = HashMap::new();
+    let mut interface_types: HashMap<String, Vec<WitTypeDef>> = HashMap::new();
     
-    // Find all WIT files in the api directory to generate stubs
+    // Find the WIT files of the interfaces the world imports to generate stubs. Other files in
+    // the api directory may belong to processes that no longer exist.
     let mut wit_files = Vec::new();
     for entry in WalkDir::new(api_dir)
         .max_depth(1)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
     {
         let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "wit") {
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "wit") {
+            let imported = path
+                .file_stem()
+                .is_some_and(|stem| interface_imports.iter().any(|i| *i == stem.to_string_lossy()));
+            if !imported {
+                continue;
+            }
             // Exclude world definition files
             if let Ok(content) = fs::read_to_string(path) {
-                if !content.contains("world ") {
+                if parse_worlds(&content).is_empty() {
                     wit_files.push(path.to_path_buf());
                 }
             }
@@ -452,55 +761,191 @@ crate-type = ["cdylib", "lib"]
     
     println!("Found {} WIT interface files", wit_files.len());
     
-    // Generate content for each module and collect types
-    let mut module_contents = HashMap::<String, String>::new();
+    // Types of the shared types interface, which the other interfaces use
+    let shared_types_file = api_dir.join(format!("{}.wit", SHARED_TYPES_INTERFACE));
+    let shared_types = if shared_types_file.exists() {
+        parse_wit_file(&shared_types_file)?.1
+    } else {
+        Vec::new()
+    };
     
+    // Parse the WIT file of each interface to extract signature structs and types
+    let mut parsed = Vec::new();
     for wit_file in &wit_files {
+        match parse_wit_file(wit_file) {
+            Ok((signatures, types)) => parsed.push((wit_file, signatures, types)),
+            Err(e) => println!("Error parsing WIT file {}: {}", wit_file.display(), e),
+        }
+    }
+
+    // Only WIT types generated from a mapping are used through the mapped Rust type; a type of a
+    // process with the same WIT name is a type of its own
+    let mapped: HashSet<(&str, &str)> = parsed
+        .iter()
+        .flat_map(|(_, _, types)| types)
+        .filter_map(|def| Some((def.name.as_str(), def.mapped_from.as_deref()?)))
+        .collect();
+    let mut config = config.clone();
+    config
+        .type_mappings
+        .retain(|m| mapped.contains(&(m.wit_name.as_str(), m.rust_path.as_str())));
+    let config = &config;
+
+    // Generate content for each module and collect types
+    let mut module_contents = BTreeMap::<String, String>::new();
+    // Cargo features gated stubs are behind
+    let mut features = BTreeSet::new();
+    
+    for (wit_file, signatures, types) in parsed {
         // Extract the interface name from the file name
         let interface_name = wit_file.file_stem().unwrap().to_string_lossy();
-        let snake_interface_name = to_snake_case(&interface_name);
+        let snake_interface_name = to_rust_identifier(to_snake_case(&interface_name));
         
         println!("Processing interface: {} -> {}", interface_name, snake_interface_name);
         
-        // Parse the WIT file to extract signature structs and types
-        match parse_wit_file(wit_file) {
-            Ok((signatures, types)) => {
-                // Store types for this interface
-                interface_types.insert(interface_name.to_string(), types);
-                
-                if signatures.is_empty() {
-                    println!("No signatures found in {}", wit_file.display());
-                    continue;
-                }
-                
-                // Generate module content
-                let mut mod_content = String::new();
-                
-                // Add function implementations
-                for signature in &signatures {
-                    let function_impl = generate_async_function(signature);
-                    mod_content.push_str(&function_impl);
-                    mod_content.push_str("\n\n");
-                }
-                
-                // Store the module content
-                module_contents.insert(snake_interface_name, mod_content);
-                
-                println!("Generated module content with {} function stubs", signatures.len());
-            },
-            Err(e) => {
-                println!("Error parsing WIT file {}: {}", wit_file.display(), e);
-            }
+        if signatures.is_empty() {
+            println!("No signatures found in {}", wit_file.display());
+            interface_types.insert(interface_name.to_string(), types);
+            continue;
+        }
+        
+        // Generate module content
+        let mut mod_content = String::new();
+        
+        // The interface's own types shadow shared ones of the same name
+        let mut visible_types = types.clone();
+        visible_types.extend(shared_types.iter().cloned());
+        
+        // Add function implementations
+        features.extend(signatures.iter().filter_map(|s| s.feature.clone()));
+        for signature in &signatures {
+            let function_impl = generate_async_function(signature, &visible_types, config);
+            mod_content.push_str(&function_impl);
+            mod_content.push_str("\n\n");
         }
+        
+        // Store the module content and the types for this interface
+        module_contents.insert(snake_interface_name, mod_content);
+        interface_types.insert(interface_name.to_string(), types);
+        
+        println!("Generated module content with {} function stubs", signatures.len());
     }
     
+    let mut dependencies = vec![
+        r#"anyhow = "1.0""#.to_string(),
+        r#"hyperware_process_lib = { version = "1.0.2", features = ["logging"] }"#.to_string(),
+        r#"process_macros = "0.1.0""#.to_string(),
+        r#"futures-util = "0.3""#.to_string(),
+        r#"serde = { version = "1.0", features = ["derive"] }"#.to_string(),
+        r#"serde_json = "1.0""#.to_string(),
+        r#"wit_parser = { path = "../crates/wit_parser" }"#.to_string(),
+        r#"once_cell = "1.20.2""#.to_string(),
+        r#"hyperware_app_common = { path = "../crates/hyperware_app_common" }"#.to_string(),
+        r#"futures = "0.3""#.to_string(),
+        r#"uuid = { version = "1.0" }"#.to_string(),
+    ];
+
+    // Add dependencies needed by mapped types, replacing defaults for the same crate
+    let crate_name = |line: &str| line.split('=').next().unwrap_or_default().trim().to_string();
+    for mapping in &config.type_mappings {
+        if let Some(dependency) = &mapping.dependency {
+            let name = crate_name(dependency);
+            dependencies.retain(|line| crate_name(line) != name);
+            dependencies.push(dependency.clone());
+        }
+    }
+
+    // Create Cargo.toml, declaring the features the stubs of gated handlers are behind
+    let features_section = if features.is_empty() {
+        String::new()
+    } else {
+        let lines: Vec<String> = features.iter().map(|f| format!("{} = []", f)).collect();
+        format!("\n[features]\n{}\n", lines.join("\n"))
+    };
+
+    let cargo_toml = format!(
+        r#"[package]
+name = "caller-utils"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+[dependencies]
+{}
+{}
+
+[lib]
+crate-type = ["cdylib", "lib"]
+"#,
+        dependencies.join("\n"),
+        features_section
+    );
+    
+    fs::write(caller_utils_dir.join("Cargo.toml"), cargo_toml)
+        .with_context(|| "Failed to write caller-utils Cargo.toml")?;
+    
+    println!("Created Cargo.toml for caller-utils");
+    
+    // Types whose serde names the bindings generated from the WIT don't reproduce are defined
+    // here with the recorded serde attributes, and so are the types that contain them
+    let all_types: Vec<&WitTypeDef> = interface_imports
+        .iter()
+        .filter_map(|interface_name| interface_types.get(interface_name))
+        .flatten()
+        .collect();
+    let mut local_types: HashSet<&str> = all_types
+        .iter()
+        .filter(|def| def.members.iter().any(|member| member.serde.is_some()))
+        .map(|def| def.name.as_str())
+        .collect();
+    loop {
+        let containing: Vec<&str> = all_types
+            .iter()
+            .filter(|def| !local_types.contains(def.name.as_str()))
+            .filter(|def| def.references().iter().any(|name| local_types.contains(name)))
+            .map(|def| def.name.as_str())
+            .collect();
+        if containing.is_empty() {
+            break;
+        }
+        local_types.extend(containing);
+    }
+
     // Create specific import statements for each interface's types
     let mut interface_use_statements = Vec::new();
+    // Deprecated types are aliased instead, since a re-export can't carry #[deprecated]
+    let mut deprecated_aliases = Vec::new();
+    let mut local_definitions = Vec::new();
+    let mut any_deprecated = false;
+    // Types several interfaces define identically are brought in once
+    let mut types_seen = HashSet::new();
     for interface_name in &interface_imports {
         if let Some(types) = interface_types.get(interface_name) {
             // Create specific imports for each type
-            for type_name in types {
-                let pascal_type = to_pascal_case(type_name);
+            for type_def in types {
+                // Mapped types are used through their own Rust type, not the WIT one
+                if config.type_mappings.iter().any(|m| m.wit_name == type_def.name) {
+                    continue;
+                }
+                if !types_seen.insert(type_def.name.as_str()) {
+                    continue;
+                }
+                let pascal_type = to_pascal_case(&type_def.name);
+                if local_types.contains(type_def.name.as_str()) {
+                    local_definitions.push(local_type_definition(type_def, config));
+                    any_deprecated |= type_def.deprecated.is_some();
+                    continue;
+                }
+                if let Some(deprecated) = &type_def.deprecated {
+                    any_deprecated = true;
+                    deprecated_aliases.push(format!(
+                        "{}pub type {} = crate::wit_custom::{};",
+                        deprecated.attribute(),
+                        pascal_type,
+                        pascal_type
+                    ));
+                    continue;
+                }
                 interface_use_statements.push(
                     format!("pub use crate::wit_custom::{};", pascal_type)
                 );
@@ -511,8 +956,13 @@ crate-type = ["cdylib", "lib"]
     // Create single lib.rs with all modules inline
     let mut lib_rs = String::new();
     
+    // The stubs use deprecated types themselves; only their callers should be warned
+    if any_deprecated {
+        lib_rs.push_str("#![allow(deprecated)]\n\n");
+    }
+    
     // First add the wit_parser macro with the correct world name
-    lib_rs.push_str(&format!("use wit_parser::wit_parser;\n"));
+    lib_rs.push_str("use wit_parser::wit_parser;\n");
     lib_rs.push_str(&format!("wit_parser!(\"api/{}.wit\");\n\n", world_name));
     
     lib_rs.push_str("/// Generated caller utilities for RPC function stubs\n\n");
@@ -520,17 +970,43 @@ crate-type = ["cdylib", "lib"]
     // Add global imports
     lib_rs.push_str("pub use hyperware_app_common::SendResult;\n");
     lib_rs.push_str("pub use hyperware_app_common::send;\n");
-    lib_rs.push_str("use hyperware_process_lib::Address;\n");
+    lib_rs.push_str("#[allow(unused_imports)]\n");
+    lib_rs.push_str("use hyperware_process_lib::{Address, Capability, LazyLoadBlob, PackageId, ProcessId};\n");
     lib_rs.push_str("use serde_json::json;\n\n");
 
     
     // Add interface use statements
+    interface_use_statements.sort();
     if !interface_use_statements.is_empty() {
         lib_rs.push_str("// Import specific types from each interface\n");
         for use_stmt in interface_use_statements {
             lib_rs.push_str(&format!("{}\n", use_stmt));
         }
-        lib_rs.push_str("\n");
+        lib_rs.push('\n');
+    }
+    
+    // Add aliases of deprecated types
+    deprecated_aliases.sort();
+    if !deprecated_aliases.is_empty() {
+        lib_rs.push_str("// Deprecated types from each interface\n");
+        for alias in deprecated_aliases {
+            lib_rs.push_str(&format!("{}\n", alias));
+        }
+        lib_rs.push('\n');
+    }
+    
+    // Fields closing a recursive type cycle hold the JSON of their value as a string
+    if local_definitions.iter().any(|definition| definition.contains(JSON_STRING_SERDE)) {
+        lib_rs.push_str(JSON_STRING_MODULE);
+        lib_rs.push('\n');
+    }
+
+    // Add the types defined with their serde names
+    if !local_definitions.is_empty() {
+        lib_rs.push_str("// Types defined with the serde names the processes use on the wire\n");
+        for definition in local_definitions {
+            lib_rs.push_str(&format!("{}\n\n", definition));
+        }
     }
     
     // Add all modules with their content
@@ -543,10 +1019,9 @@ crate-type = ["cdylib", "lib"]
     }
     
     // Write lib.rs
-    let lib_rs_path = caller_utils_dir.join("src").join("lib.rs");
     println!("Writing lib.rs to {}", lib_rs_path.display());
     
-    fs::write(&lib_rs_path, lib_rs)
+    fs::write(&lib_rs_path, with_header(&lib_rs, &input_hash(base_dir, projects, &config.cfg)))
         .with_context(|| format!("Failed to write lib.rs: {}", lib_rs_path.display()))?;
     
     println!("Created single lib.rs file with all modules inline");
@@ -563,19 +1038,22 @@ crate-type = ["cdylib", "lib"]
     
     fs::create_dir_all(&target_wit_dir)?;
     
-    // Copy all WIT files to target/wit
+    // Copy all WIT files to target/wit, including the packages under deps/
     for entry in WalkDir::new(api_dir)
-        .max_depth(1)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
     {
         let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "wit") {
-            let file_name = path.file_name().unwrap();
-            let target_path = target_wit_dir.join(file_name);
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "wit") {
+            let relative_path = path.strip_prefix(api_dir)?;
+            let target_path = target_wit_dir.join(relative_path);
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
             fs::copy(path, &target_path)
                 .with_context(|| format!("Failed to copy {} to {}", path.display(), target_path.display()))?;
-            println!("Copied {} to target/wit directory", file_name.to_string_lossy());
+            println!("Copied {} to target/wit directory", relative_path.display());
         }
     }
     
@@ -583,7 +1061,7 @@ crate-type = ["cdylib", "lib"]
 }
 
 // Update workspace Cargo.toml to include the caller-utils crate
-fn update_workspace_cargo_toml(base_dir: &Path) -> Result<()> {
+fn update_workspace_cargo_toml(base_dir: &Path, manifest: &mut Manifest) -> Result<()> {
     let workspace_cargo_toml = base_dir.join("Cargo.toml");
     println!("Updating workspace Cargo.toml at {}", workspace_cargo_toml.display());
     
@@ -605,7 +1083,7 @@ fn update_workspace_cargo_toml(base_dir: &Path) -> Result<()> {
             if let Some(members_array) = members.as_array_mut() {
                 // Check if caller-utils is already in the members list
                 let caller_utils_exists = members_array.iter().any(|m| {
-                    m.as_str().map_or(false, |s| s == "caller-utils")
+                    m.as_str() == Some("caller-utils")
                 });
                 
                 if !caller_utils_exists {
@@ -619,6 +1097,7 @@ fn update_workspace_cargo_toml(base_dir: &Path) -> Result<()> {
                     fs::write(&workspace_cargo_toml, updated_content)
                         .with_context(|| format!("Failed to write updated workspace Cargo.toml: {}", workspace_cargo_toml.display()))?;
                     
+                    manifest.workspace_member = true;
                     println!("Successfully updated workspace Cargo.toml");
                 } else {
                     println!("caller-utils is already in workspace members");
@@ -631,7 +1110,7 @@ fn update_workspace_cargo_toml(base_dir: &Path) -> Result<()> {
 }
 
 // Add caller-utils as a dependency to hyperware:process crates
-fn add_caller_utils_to_projects(projects: &[PathBuf]) -> Result<()> {
+fn add_caller_utils_to_projects(base_dir: &Path, projects: &[PathBuf], manifest: &mut Manifest) -> Result<()> {
     for project_path in projects {
         let cargo_toml_path = project_path.join("Cargo.toml");
         println!("Adding caller-utils dependency to {}", cargo_toml_path.display());
@@ -662,6 +1141,7 @@ fn add_caller_utils_to_projects(projects: &[PathBuf]) -> Result<()> {
                     fs::write(&cargo_toml_path, updated_content)
                         .with_context(|| format!("Failed to write updated project Cargo.toml: {}", cargo_toml_path.display()))?;
                     
+                    manifest.dependencies.insert(Manifest::relative(base_dir, &cargo_toml_path));
                     println!("Successfully added caller-utils dependency");
                 } else {
                     println!("caller-utils dependency already exists");
@@ -674,15 +1154,77 @@ fn add_caller_utils_to_projects(projects: &[PathBuf]) -> Result<()> {
 }
 
 // Create caller-utils crate and integrate with the workspace
-pub fn create_caller_utils(base_dir: &Path, api_dir: &Path, projects: &[PathBuf]) -> Result<()> {
+pub fn create_caller_utils(
+    base_dir: &Path,
+    api_dir: &Path,
+    projects: &[PathBuf],
+    config: &Config,
+    manifest: &mut Manifest,
+    force: bool,
+) -> Result<()> {
     // Step 1: Create the caller-utils crate
-    create_caller_utils_crate(api_dir, base_dir)?;
+    create_caller_utils_crate(api_dir, base_dir, projects, config, manifest, force)?;
     
     // Step 2: Update workspace Cargo.toml
-    update_workspace_cargo_toml(base_dir)?;
+    update_workspace_cargo_toml(base_dir, manifest)?;
     
     // Step 3: Add caller-utils dependency to each hyperware:process project
-    add_caller_utils_to_projects(projects)?;
+    add_caller_utils_to_projects(base_dir, projects, manifest)?;
+    
+    Ok(())
+}
+// Remove caller-utils from the workspace members, undoing update_workspace_cargo_toml
+pub fn remove_from_workspace_cargo_toml(base_dir: &Path) -> Result<()> {
+    let workspace_cargo_toml = base_dir.join("Cargo.toml");
+    if !workspace_cargo_toml.exists() {
+        return Ok(());
+    }
+    
+    let content = fs::read_to_string(&workspace_cargo_toml)
+        .with_context(|| format!("Failed to read workspace Cargo.toml: {}", workspace_cargo_toml.display()))?;
+    let mut parsed_toml: Value = content.parse()
+        .with_context(|| "Failed to parse workspace Cargo.toml")?;
+    
+    let members = parsed_toml
+        .get_mut("workspace")
+        .and_then(|w| w.get_mut("members"))
+        .and_then(Value::as_array_mut);
+    if let Some(members_array) = members {
+        let count = members_array.len();
+        members_array.retain(|m| m.as_str() != Some("caller-utils"));
+        if members_array.len() != count {
+            println!("Removing caller-utils from workspace members");
+            let updated_content = toml::to_string_pretty(&parsed_toml)
+                .with_context(|| "Failed to serialize updated workspace Cargo.toml")?;
+            fs::write(&workspace_cargo_toml, updated_content)
+                .with_context(|| format!("Failed to write updated workspace Cargo.toml: {}", workspace_cargo_toml.display()))?;
+        }
+    }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+// Remove the caller-utils dependency from a project's Cargo.toml, undoing add_caller_utils_to_projects
+pub fn remove_caller_utils_dependency(cargo_toml_path: &Path) -> Result<()> {
+    if !cargo_toml_path.exists() {
+        return Ok(());
+    }
+    
+    let content = fs::read_to_string(cargo_toml_path)
+        .with_context(|| format!("Failed to read project Cargo.toml: {}", cargo_toml_path.display()))?;
+    let mut parsed_toml: Value = content.parse()
+        .with_context(|| format!("Failed to parse project Cargo.toml: {}", cargo_toml_path.display()))?;
+    
+    let dependencies = parsed_toml.get_mut("dependencies").and_then(Value::as_table_mut);
+    if let Some(deps_table) = dependencies {
+        if deps_table.remove("caller-utils").is_some() {
+            println!("Removing caller-utils dependency from {}", cargo_toml_path.display());
+            let updated_content = toml::to_string_pretty(&parsed_toml)
+                .with_context(|| format!("Failed to serialize updated project Cargo.toml: {}", cargo_toml_path.display()))?;
+            fs::write(cargo_toml_path, updated_content)
+                .with_context(|| format!("Failed to write updated project Cargo.toml: {}", cargo_toml_path.display()))?;
+        }
+    }
+    
+    Ok(())
+}
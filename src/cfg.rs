@@ -0,0 +1,481 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+use syn::{Attribute, ImplItem, Item, Meta, Token};
+use toml::Value;
+
+use crate::config::CfgConfig;
+
+// Attribute put on handlers kept behind a feature gate in place of their
+// `#[cfg(feature = "...")]`, e.g. `#[hyper_bindgen_feature = "admin"]`
+const FEATURE_GATE_ATTR: &str = "hyper_bindgen_feature";
+
+// cfg options of the wasm32-wasip1 target processes are built for
+const TARGET_CFG: &[(&str, &str)] = &[
+    ("target_arch", "wasm32"),
+    ("target_family", "wasm"),
+    ("target_os", "wasi"),
+    ("target_pointer_width", "32"),
+];
+
+// Parse a `--cfg` option: `name`, `name=value` or `name="value"`
+pub fn parse_cfg_option(option: &str) -> (String, Option<String>) {
+    match option.split_once('=') {
+        Some((name, value)) => (name.trim().to_string(), Some(value.trim().trim_matches('"').to_string())),
+        None => (option.trim().to_string(), None),
+    }
+}
+
+// The configuration a project is built with
+pub struct ActiveCfg {
+    features: HashSet<String>,
+    // Other cfg options, as names and `name = "value"` pairs
+    options: HashSet<(String, Option<String>)>,
+    // Keep items behind a single feature, gating the handlers among them
+    feature_gates: bool,
+}
+
+impl ActiveCfg {
+    // The requested features of a project, its default features unless disabled, and the
+    // features those enable in turn
+    pub fn for_project(project_path: &Path, cfg: &CfgConfig) -> Result<Self> {
+        let cargo_toml_path = project_path.join("Cargo.toml");
+        let cargo_toml: Value = fs::read_to_string(&cargo_toml_path)
+            .with_context(|| format!("Failed to read {}", cargo_toml_path.display()))?
+            .parse()
+            .with_context(|| format!("Failed to parse {}", cargo_toml_path.display()))?;
+        let declared: BTreeMap<String, Vec<String>> = cargo_toml
+            .get("features")
+            .and_then(Value::as_table)
+            .map(|features| {
+                features
+                    .iter()
+                    .map(|(name, enables)| {
+                        let enables = enables
+                            .as_array()
+                            .map(|e| e.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                            .unwrap_or_default();
+                        (name.clone(), enables)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for feature in &cfg.features {
+            if !declared.contains_key(feature) {
+                println!("Warning: feature '{}' is not declared in {}", feature, cargo_toml_path.display());
+            }
+        }
+
+        let mut pending = cfg.features.clone();
+        if cfg.default_features && declared.contains_key("default") {
+            pending.push("default".to_string());
+        }
+        let mut features = HashSet::new();
+        while let Some(feature) = pending.pop() {
+            if !features.insert(feature.clone()) {
+                continue;
+            }
+            // Entries like `dep:crate` and `crate/feature` configure dependencies
+            for enabled in declared.get(&feature).into_iter().flatten() {
+                if !enabled.contains(':') && !enabled.contains('/') {
+                    pending.push(enabled.clone());
+                }
+            }
+        }
+        let mut sorted: Vec<&String> = features.iter().collect();
+        sorted.sort();
+        println!("Active features: {:?}", sorted);
+
+        let mut options: HashSet<(String, Option<String>)> = TARGET_CFG
+            .iter()
+            .map(|(name, value)| (name.to_string(), Some(value.to_string())))
+            .collect();
+        options.extend(cfg.options.iter().cloned());
+
+        Ok(ActiveCfg { features, options, feature_gates: cfg.feature_gates })
+    }
+
+    // Evaluate a cfg predicate like `all(feature = "a", not(test))`
+    fn eval(&self, predicate: &Meta) -> Result<bool> {
+        let name = predicate
+            .path()
+            .get_ident()
+            .map(|ident| ident.to_string())
+            .context("cfg predicates must be plain identifiers")?;
+        match predicate {
+            Meta::Path(_) => Ok(self.options.contains(&(name, None))),
+            Meta::NameValue(meta) => {
+                let syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(value), .. }) = &meta.value else {
+                    anyhow::bail!("Error: the value of cfg option `{}` must be a string", name);
+                };
+                if name == "feature" {
+                    Ok(self.features.contains(&value.value()))
+                } else {
+                    Ok(self.options.contains(&(name, Some(value.value()))))
+                }
+            }
+            Meta::List(list) => {
+                let nested = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+                let mut results = Vec::new();
+                for predicate in &nested {
+                    results.push(self.eval(predicate)?);
+                }
+                match name.as_str() {
+                    "all" => Ok(results.iter().all(|r| *r)),
+                    "any" => Ok(results.iter().any(|r| *r)),
+                    "not" if results.len() == 1 => Ok(!results[0]),
+                    _ => anyhow::bail!("Error: unsupported cfg predicate `{}(...)`", name),
+                }
+            }
+        }
+    }
+
+    // Replace each `#[cfg_attr(predicate, attrs...)]` by its attributes if the predicate holds,
+    // and drop it otherwise
+    fn expand_cfg_attr(&self, attrs: &mut Vec<Attribute>) -> Result<()> {
+        while attrs.iter().any(|attr| attr.path().is_ident("cfg_attr")) {
+            let mut expanded = Vec::new();
+            for attr in attrs.drain(..) {
+                if !attr.path().is_ident("cfg_attr") {
+                    expanded.push(attr);
+                    continue;
+                }
+                let nested = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+                let mut nested = nested.into_iter();
+                let predicate = nested.next().context("cfg_attr without a predicate")?;
+                if self.eval(&predicate)? {
+                    expanded.extend(nested.map(|meta| Attribute { meta, ..attr.clone() }));
+                }
+            }
+            *attrs = expanded;
+        }
+        Ok(())
+    }
+
+    // The feature of a `#[cfg(feature = "...")]` on its own, if items behind features are kept
+    fn gate_feature(&self, attrs: &[Attribute]) -> Option<String> {
+        if !self.feature_gates {
+            return None;
+        }
+        let mut cfgs = attrs.iter().filter(|attr| attr.path().is_ident("cfg"));
+        let (Some(cfg), None) = (cfgs.next(), cfgs.next()) else {
+            return None;
+        };
+        let Ok(Meta::NameValue(meta)) = cfg.parse_args::<Meta>() else {
+            return None;
+        };
+        match &meta.value {
+            syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(value), .. }) if meta.path.is_ident("feature") => {
+                Some(value.value())
+            }
+            _ => None,
+        }
+    }
+
+    // Whether something with these attributes is compiled in, after expanding its cfg_attr.
+    // Items behind a single feature are kept if feature gates are on (`gated`), since gated
+    // handlers may use them.
+    fn keep(&self, attrs: &mut Vec<Attribute>, gated: bool) -> Result<bool> {
+        self.expand_cfg_attr(attrs)?;
+        if gated && self.gate_feature(attrs).is_some() {
+            return Ok(true);
+        }
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("cfg")) {
+            if !self.eval(&attr.parse_args::<Meta>()?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Like `keep`, but an item kept behind a feature gets the gate attribute in place of its cfg
+    fn keep_gated(&self, attrs: &mut Vec<Attribute>) -> Result<bool> {
+        self.expand_cfg_attr(attrs)?;
+        if let Some(feature) = self.gate_feature(attrs) {
+            println!("    Gating handler behind feature '{}'", feature);
+            attrs.retain(|attr| !attr.path().is_ident("cfg"));
+            attrs.extend(
+                Attribute::parse_outer.parse_str(&format!("#[{} = {:?}]", FEATURE_GATE_ATTR, feature))?,
+            );
+            return Ok(true);
+        }
+        self.keep(attrs, false)
+    }
+}
+
+// The feature a handler was kept behind, if any
+pub fn feature_gate(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().find(|attr| attr.path().is_ident(FEATURE_GATE_ATTR)).and_then(|attr| {
+        match &attr.meta {
+            Meta::NameValue(syn::MetaNameValue {
+                value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(value), .. }),
+                ..
+            }) => Some(value.value()),
+            _ => None,
+        }
+    })
+}
+
+fn item_attrs(item: &mut Item) -> Option<&mut Vec<Attribute>> {
+    match item {
+        Item::Const(item) => Some(&mut item.attrs),
+        Item::Enum(item) => Some(&mut item.attrs),
+        Item::Fn(item) => Some(&mut item.attrs),
+        Item::Impl(item) => Some(&mut item.attrs),
+        Item::Macro(item) => Some(&mut item.attrs),
+        Item::Mod(item) => Some(&mut item.attrs),
+        Item::Static(item) => Some(&mut item.attrs),
+        Item::Struct(item) => Some(&mut item.attrs),
+        Item::Trait(item) => Some(&mut item.attrs),
+        Item::Type(item) => Some(&mut item.attrs),
+        Item::Use(item) => Some(&mut item.attrs),
+        _ => None,
+    }
+}
+
+fn impl_item_attrs(item: &mut ImplItem) -> Option<&mut Vec<Attribute>> {
+    match item {
+        ImplItem::Const(item) => Some(&mut item.attrs),
+        ImplItem::Fn(item) => Some(&mut item.attrs),
+        ImplItem::Macro(item) => Some(&mut item.attrs),
+        ImplItem::Type(item) => Some(&mut item.attrs),
+        _ => None,
+    }
+}
+
+// Removes what the active configuration compiles out, keeping the first error
+struct CfgPruner<'a> {
+    cfg: &'a ActiveCfg,
+    error: Option<anyhow::Error>,
+}
+
+impl CfgPruner<'_> {
+    fn keep(&mut self, attrs: Option<&mut Vec<Attribute>>, gated: bool) -> bool {
+        let Some(attrs) = attrs else {
+            return true;
+        };
+        match self.cfg.keep(attrs, gated) {
+            Ok(keep) => keep,
+            Err(e) => {
+                self.error.get_or_insert(e);
+                true
+            }
+        }
+    }
+
+    fn retain_items(&mut self, items: &mut Vec<Item>) {
+        items.retain_mut(|item| self.keep(item_attrs(item), true));
+    }
+
+    fn retain<T, P: Default>(
+        &mut self,
+        punctuated: &mut Punctuated<T, P>,
+        attrs: impl Fn(&mut T) -> &mut Vec<Attribute>,
+    ) {
+        let mut kept: Vec<T> = std::mem::take(punctuated).into_iter().collect();
+        kept.retain_mut(|node| self.keep(Some(attrs(node)), false));
+        *punctuated = kept.into_iter().collect();
+    }
+}
+
+impl VisitMut for CfgPruner<'_> {
+    fn visit_file_mut(&mut self, file: &mut syn::File) {
+        self.retain_items(&mut file.items);
+        visit_mut::visit_file_mut(self, file);
+    }
+
+    fn visit_item_mod_mut(&mut self, item_mod: &mut syn::ItemMod) {
+        if let Some((_, items)) = &mut item_mod.content {
+            self.retain_items(items);
+        }
+        visit_mut::visit_item_mod_mut(self, item_mod);
+    }
+
+    fn visit_item_impl_mut(&mut self, item_impl: &mut syn::ItemImpl) {
+        let cfg = self.cfg;
+        item_impl.items.retain_mut(|item| {
+            let Some(attrs) = impl_item_attrs(item) else {
+                return true;
+            };
+            match cfg.keep_gated(attrs) {
+                Ok(keep) => keep,
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                    true
+                }
+            }
+        });
+        visit_mut::visit_item_impl_mut(self, item_impl);
+    }
+
+    fn visit_item_enum_mut(&mut self, item_enum: &mut syn::ItemEnum) {
+        self.retain(&mut item_enum.variants, |variant| &mut variant.attrs);
+        visit_mut::visit_item_enum_mut(self, item_enum);
+    }
+
+    fn visit_fields_named_mut(&mut self, fields: &mut syn::FieldsNamed) {
+        self.retain(&mut fields.named, |field| &mut field.attrs);
+        visit_mut::visit_fields_named_mut(self, fields);
+    }
+
+    fn visit_fields_unnamed_mut(&mut self, fields: &mut syn::FieldsUnnamed) {
+        self.retain(&mut fields.unnamed, |field| &mut field.attrs);
+        visit_mut::visit_fields_unnamed_mut(self, fields);
+    }
+
+    fn visit_signature_mut(&mut self, signature: &mut syn::Signature) {
+        self.retain(&mut signature.inputs, |arg| match arg {
+            syn::FnArg::Receiver(receiver) => &mut receiver.attrs,
+            syn::FnArg::Typed(pat_type) => &mut pat_type.attrs,
+        });
+        visit_mut::visit_signature_mut(self, signature);
+    }
+}
+
+// Remove the items, methods, fields, variants and parameters the active configuration compiles
+// out, and apply the `cfg_attr` attributes that hold
+pub fn apply_cfg(ast: &mut syn::File, cfg: &ActiveCfg) -> Result<()> {
+    let mut pruner = CfgPruner { cfg, error: None };
+    pruner.visit_file_mut(ast);
+    match pruner.error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active(features: &[&str], options: &[(&str, Option<&str>)], feature_gates: bool) -> ActiveCfg {
+        ActiveCfg {
+            features: features.iter().map(|f| f.to_string()).collect(),
+            options: options.iter().map(|(n, v)| (n.to_string(), v.map(str::to_string))).collect(),
+            feature_gates,
+        }
+    }
+
+    fn eval(cfg: &ActiveCfg, predicate: &str) -> bool {
+        cfg.eval(&syn::parse_str::<Meta>(predicate).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn predicates_follow_features_and_options() {
+        let cfg = active(&["admin"], &[("debug_tools", None), ("env", Some("staging"))], false);
+        assert!(eval(&cfg, r#"feature = "admin""#));
+        assert!(!eval(&cfg, r#"feature = "metrics""#));
+        assert!(eval(&cfg, "debug_tools"));
+        assert!(eval(&cfg, r#"env = "staging""#));
+        assert!(!eval(&cfg, "test"));
+        assert!(eval(&cfg, r#"all(feature = "admin", not(test))"#));
+        assert!(eval(&cfg, r#"any(test, env = "staging")"#));
+        assert!(!eval(&cfg, r#"all(debug_tools, feature = "metrics")"#));
+        assert!(cfg.eval(&syn::parse_str::<Meta>("none(test)").unwrap()).is_err());
+    }
+
+    #[test]
+    fn options_are_parsed_with_or_without_quotes() {
+        assert_eq!(parse_cfg_option("debug_tools"), ("debug_tools".to_string(), None));
+        assert_eq!(parse_cfg_option("env=staging"), ("env".to_string(), Some("staging".to_string())));
+        assert_eq!(parse_cfg_option(r#"env = "staging""#), ("env".to_string(), Some("staging".to_string())));
+    }
+
+    #[test]
+    fn features_enable_the_features_they_list() {
+        let dir = std::env::temp_dir().join(format!("hyper-bindgen-cfg-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[features]\ndefault = [\"metrics\"]\nmetrics = [\"dep:prometheus\"]\nadmin = [\"audit\", \"serde/std\"]\naudit = []\n",
+        )
+        .unwrap();
+        let config = CfgConfig { features: vec!["admin".to_string()], ..CfgConfig::default() };
+        let cfg = ActiveCfg::for_project(&dir, &config).unwrap();
+        let mut features: Vec<&String> = cfg.features.iter().collect();
+        features.sort();
+        assert_eq!(features, ["admin", "audit", "default", "metrics"]);
+
+        let config = CfgConfig { default_features: false, ..CfgConfig::default() };
+        assert!(ActiveCfg::for_project(&dir, &config).unwrap().features.is_empty());
+        assert!(cfg.options.contains(&("target_arch".to_string(), Some("wasm32".to_string()))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compiled_out_code_is_pruned_and_cfg_attr_applied() {
+        let mut ast: syn::File = syn::parse_str(
+            r#"
+            #[cfg(test)]
+            struct TestOnly;
+
+            #[cfg_attr(feature = "admin", derive(Debug))]
+            #[cfg_attr(test, derive(Clone))]
+            struct Item {
+                id: u64,
+                #[cfg(feature = "admin")]
+                owner: String,
+                #[cfg(not(feature = "admin"))]
+                public: bool,
+            }
+
+            enum Kind {
+                A,
+                #[cfg(test)]
+                B,
+            }
+
+            impl State {
+                fn ping(&self, #[cfg(test)] trace: bool) {}
+                #[cfg(feature = "metrics")]
+                fn metrics(&self) {}
+            }
+            "#,
+        )
+        .unwrap();
+        apply_cfg(&mut ast, &active(&["admin"], &[], false)).unwrap();
+
+        assert_eq!(ast.items.len(), 3);
+        let Item::Struct(item) = &ast.items[0] else { panic!("expected the Item struct") };
+        let derives: Vec<String> = item.attrs.iter().map(|a| a.path().get_ident().unwrap().to_string()).collect();
+        assert_eq!(derives, ["derive"]);
+        let fields: Vec<String> = item.fields.iter().map(|f| f.ident.as_ref().unwrap().to_string()).collect();
+        assert_eq!(fields, ["id", "owner"]);
+        let Item::Enum(kind) = &ast.items[1] else { panic!("expected the Kind enum") };
+        assert_eq!(kind.variants.len(), 1);
+        let Item::Impl(state) = &ast.items[2] else { panic!("expected the impl") };
+        assert_eq!(state.items.len(), 1);
+        let ImplItem::Fn(ping) = &state.items[0] else { panic!("expected ping") };
+        assert_eq!(ping.sig.inputs.len(), 1);
+    }
+
+    #[test]
+    fn handlers_behind_one_feature_are_gated_when_feature_gates_are_on() {
+        let mut ast: syn::File = syn::parse_str(
+            r#"
+            #[cfg(feature = "admin")]
+            struct AdminReport;
+
+            impl State {
+                #[cfg(feature = "admin")]
+                fn reset(&self) {}
+                #[cfg(all(feature = "admin", test))]
+                fn debug(&self) {}
+            }
+            "#,
+        )
+        .unwrap();
+        apply_cfg(&mut ast, &active(&[], &[], true)).unwrap();
+
+        assert_eq!(ast.items.len(), 2);
+        let Item::Impl(state) = &ast.items[1] else { panic!("expected the impl") };
+        assert_eq!(state.items.len(), 1);
+        let ImplItem::Fn(reset) = &state.items[0] else { panic!("expected reset") };
+        assert_eq!(feature_gate(&reset.attrs).as_deref(), Some("admin"));
+        assert!(!reset.attrs.iter().any(|attr| attr.path().is_ident("cfg")));
+    }
+}
@@ -0,0 +1,451 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use toml::Value;
+
+use crate::cfg::parse_cfg_option;
+use crate::naming::to_kebab_case;
+
+// Name of the optional configuration file in the workspace root
+pub const CONFIG_FILE_NAME: &str = "hyper-bindgen.toml";
+
+// The WIT definition a mapped Rust type is emitted as
+#[derive(Debug, Clone)]
+pub enum WitMapping {
+    // `type name = <wit type>;`
+    Alias(String),
+    // `record name { field: type, ... }`
+    Record(Vec<(String, String)>),
+}
+
+// Maps a third-party or std Rust type onto a WIT type, and back to a Rust type for callers
+#[derive(Debug, Clone)]
+pub struct TypeMapping {
+    // Path of the Rust type, e.g. `uuid::Uuid`; uses match on any suffix of the path
+    pub rust_path: String,
+    // Name of the WIT type emitted for it, e.g. `uuid`
+    pub wit_name: String,
+    // What the WIT type is defined as, following the type's serde representation
+    pub wit: WitMapping,
+    // Rust type used in the caller stubs, e.g. `uuid::Uuid`
+    pub caller_type: String,
+    // Expression used when a caller stub needs a placeholder value, if not `Default`
+    pub default_value: Option<String>,
+    // Dependency line added to caller-utils' Cargo.toml, e.g. `uuid = "1.0"`
+    pub dependency: Option<String>,
+}
+
+impl TypeMapping {
+    fn alias(rust_path: &str, wit: &str, default_value: Option<&str>) -> Self {
+        let last_segment = rust_path.rsplit("::").next().unwrap_or(rust_path);
+        TypeMapping {
+            rust_path: rust_path.to_string(),
+            wit_name: to_kebab_case(last_segment),
+            wit: WitMapping::Alias(wit.to_string()),
+            caller_type: rust_path.to_string(),
+            default_value: default_value.map(|d| d.to_string()),
+            dependency: None,
+        }
+    }
+
+    fn record(rust_path: &str, fields: &[(&str, &str)], default_value: Option<&str>) -> Self {
+        let mut mapping = TypeMapping::alias(rust_path, "", default_value);
+        mapping.wit = WitMapping::Record(
+            fields.iter().map(|(name, ty)| (name.to_string(), ty.to_string())).collect(),
+        );
+        mapping
+    }
+
+    // Whether a (possibly shortened) path written in the source refers to this type
+    pub fn matches(&self, segments: &[String]) -> bool {
+        let mapped: Vec<&str> = self.rust_path.split("::").collect();
+        segments.len() <= mapped.len()
+            && mapped[mapped.len() - segments.len()..]
+                .iter()
+                .zip(segments)
+                .all(|(a, b)| a == b)
+    }
+}
+
+// Mappings for std types, following how serde serializes them
+fn builtin_type_mappings() -> Vec<TypeMapping> {
+    let mut mappings = vec![
+        TypeMapping::record(
+            "std::time::Duration",
+            &[("secs", "u64"), ("nanos", "u32")],
+            None,
+        ),
+        TypeMapping::record(
+            "std::time::SystemTime",
+            &[("secs-since-epoch", "u64"), ("nanos-since-epoch", "u32")],
+            Some("std::time::SystemTime::UNIX_EPOCH"),
+        ),
+        TypeMapping::alias("std::path::PathBuf", "string", None),
+        TypeMapping::alias(
+            "std::net::IpAddr",
+            "string",
+            Some("std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED)"),
+        ),
+        TypeMapping::alias("std::net::Ipv4Addr", "string", Some("std::net::Ipv4Addr::UNSPECIFIED")),
+        TypeMapping::alias("std::net::Ipv6Addr", "string", Some("std::net::Ipv6Addr::UNSPECIFIED")),
+        TypeMapping::alias(
+            "std::net::SocketAddr",
+            "string",
+            Some("std::net::SocketAddr::from(([0, 0, 0, 0], 0))"),
+        ),
+        TypeMapping::alias(
+            "std::net::SocketAddrV4",
+            "string",
+            Some("std::net::SocketAddrV4::new(std::net::Ipv4Addr::UNSPECIFIED, 0)"),
+        ),
+        TypeMapping::alias(
+            "std::net::SocketAddrV6",
+            "string",
+            Some("std::net::SocketAddrV6::new(std::net::Ipv6Addr::UNSPECIFIED, 0, 0, 0)"),
+        ),
+    ];
+
+    // NonZero integers serialize as the plain integer
+    for (rust, wit) in [
+        ("U8", "u8"), ("U16", "u16"), ("U32", "u32"), ("U64", "u64"),
+        ("I8", "s8"), ("I16", "s16"), ("I32", "s32"), ("I64", "s64"),
+    ] {
+        let path = format!("std::num::NonZero{}", rust);
+        let min = format!("{}::MIN", path);
+        mappings.push(TypeMapping::alias(&path, wit, Some(&min)));
+    }
+
+    mappings
+}
+
+// How a name segment starting with a digit, which WIT doesn't allow, is mangled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigitMangling {
+    // Join it onto the previous segment, e.g. `enable_2fa` -> `enable2fa`
+    Merge,
+    // Spell out its leading digits, e.g. `enable_2fa` -> `enable-two-fa`
+    Spell,
+    // Reject the name
+    Error,
+}
+
+// How Rust names are turned into WIT identifiers
+#[derive(Debug, Clone)]
+pub struct NamingConfig {
+    // Mangling for segments starting with a digit
+    pub digits: DigitMangling,
+    // Explicit WIT names, keyed by the Rust (or serde) name they replace
+    pub renames: HashMap<String, String>,
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        NamingConfig {
+            digits: DigitMangling::Merge,
+            renames: HashMap::new(),
+        }
+    }
+}
+
+// Package the generated WIT files are declared in; unset parts are taken from existing WIT
+// files or the Cargo metadata of the processes
+#[derive(Debug, Clone, Default)]
+pub struct PackageConfig {
+    pub namespace: Option<String>,
+    pub name: Option<String>,
+    pub version: Option<String>,
+}
+
+// Items added to a world besides the imports of the generated interfaces, written as in WIT,
+// e.g. `wasi:http/outgoing-handler@0.2.0`
+#[derive(Debug, Clone, Default)]
+pub struct WorldConfig {
+    pub includes: Vec<String>,
+    pub imports: Vec<String>,
+    pub exports: Vec<String>,
+}
+
+impl WorldConfig {
+    // The configured items as WIT statements, includes first
+    pub fn statements(&self) -> Vec<String> {
+        let includes = self.includes.iter().map(|i| format!("include {};", i));
+        let imports = self.imports.iter().map(|i| format!("import {};", i));
+        let exports = self.exports.iter().map(|e| format!("export {};", e));
+        includes.chain(imports).chain(exports).collect()
+    }
+}
+
+// WIT feature gates put on `#[deprecated]` items, which WIT only allows to deprecate items that
+// are gated as stable since a version or as unstable behind a feature
+#[derive(Debug, Clone, Default)]
+pub struct DeprecationConfig {
+    // Version deprecated items are `@since`; defaults to the package version
+    pub since: Option<String>,
+    // Feature deprecated items are `@unstable` behind, instead of `@since`
+    pub feature: Option<String>,
+}
+
+// The configuration `#[cfg]` and `#[cfg_attr]` are evaluated against, from `[cfg]` and the
+// command line
+#[derive(Debug, Clone)]
+pub struct CfgConfig {
+    // Features enabled on top of the default ones
+    pub features: Vec<String>,
+    // Whether the `default` feature is enabled
+    pub default_features: bool,
+    // Other cfg options, e.g. `("debug_assertions", None)` or `("target_env", Some("p1"))`
+    pub options: Vec<(String, Option<String>)>,
+    // Keep handlers behind a single feature, gating them as `@unstable` in the WIT and with
+    // `#[cfg(feature = ...)]` in caller-utils
+    pub feature_gates: bool,
+}
+
+impl Default for CfgConfig {
+    fn default() -> Self {
+        CfgConfig {
+            features: Vec::new(),
+            default_features: true,
+            options: Vec::new(),
+            feature_gates: false,
+        }
+    }
+}
+
+// User configuration for hyper-bindgen, loaded from hyper-bindgen.toml
+#[derive(Debug, Clone)]
+pub struct Config {
+    // Inline Rust type aliases at their use sites instead of emitting WIT `type` definitions
+    pub inline_type_aliases: bool,
+    // Template for records generated for struct-like enum variants, using `{enum}` and `{variant}`
+    pub variant_record_name: String,
+    // Built-in and user-declared mappings for types that aren't declared in the project
+    pub type_mappings: Vec<TypeMapping>,
+    // WIT type used for the field closing a recursive type cycle; only `string`, holding its JSON
+    pub recursive_fallback: Option<String>,
+    // How Rust names are turned into WIT identifiers
+    pub naming: NamingConfig,
+    // Package the generated WIT files are declared in
+    pub package: PackageConfig,
+    // Extra items of each world, by world name
+    pub worlds: BTreeMap<String, WorldConfig>,
+    // Feature gates of deprecated items
+    pub deprecation: DeprecationConfig,
+    // Active features and cfg options
+    pub cfg: CfgConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            inline_type_aliases: false,
+            variant_record_name: "{enum}-{variant}".to_string(),
+            type_mappings: builtin_type_mappings(),
+            recursive_fallback: None,
+            naming: NamingConfig::default(),
+            package: PackageConfig::default(),
+            worlds: BTreeMap::new(),
+            deprecation: DeprecationConfig::default(),
+            cfg: CfgConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    // Load the configuration from the workspace root, falling back to defaults if absent
+    pub fn load(base_dir: &Path) -> Result<Self> {
+        let config_path = base_dir.join(CONFIG_FILE_NAME);
+        if !config_path.exists() {
+            println!("No {} found, using default configuration", CONFIG_FILE_NAME);
+            return Ok(Config::default());
+        }
+
+        println!("Loading configuration from {}", config_path.display());
+        let content = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+
+        let parsed: Value = content.parse()
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+
+        let parsed = Section::new(String::new(), &parsed, &["types", "naming", "package", "deprecation", "cfg", "worlds"])?;
+        let mut config = Config::default();
+
+        // [types] section
+        if let Some(types) = parsed.section("types", &["inline-aliases", "variant-record-name", "recursive-fallback", "mappings"])? {
+            if let Some(inline) = types.boolean("inline-aliases")? {
+                config.inline_type_aliases = inline;
+            }
+            if let Some(template) = types.string("variant-record-name")? {
+                if !template.contains("{variant}") {
+                    anyhow::bail!("types.variant-record-name must contain the `{{variant}}` placeholder");
+                }
+                config.variant_record_name = template;
+            }
+            if let Some(fallback) = types.string("recursive-fallback")? {
+                // Callers convert the field to and from the JSON the process sends, which only a
+                // string can hold
+                if fallback != "string" {
+                    anyhow::bail!("types.recursive-fallback must be \"string\", got \"{}\"", fallback);
+                }
+                config.recursive_fallback = Some(fallback);
+            }
+            for (rust_path, mapping) in types.entries("mappings")? {
+                let mapping = parse_type_mapping(rust_path, mapping)?;
+                println!("  Type mapping: {} -> {}", mapping.rust_path, mapping.wit_name);
+                // User mappings replace built-in ones for the same type
+                config.type_mappings.retain(|m| m.rust_path != mapping.rust_path);
+                config.type_mappings.push(mapping);
+            }
+        }
+
+        // [naming] section
+        if let Some(naming) = parsed.section("naming", &["digits", "renames"])? {
+            if let Some(digits) = naming.string("digits")? {
+                config.naming.digits = match digits.as_str() {
+                    "merge" => DigitMangling::Merge,
+                    "spell" => DigitMangling::Spell,
+                    "error" => DigitMangling::Error,
+                    _ => anyhow::bail!("naming.digits must be one of \"merge\", \"spell\" or \"error\""),
+                };
+            }
+            for (name, wit_name) in naming.entries("renames")? {
+                let wit_name = wit_name.as_str()
+                    .with_context(|| format!("naming.renames.\"{}\" must be a string", name))?;
+                println!("  Rename: {} -> {}", name, wit_name);
+                config.naming.renames.insert(name.clone(), wit_name.to_string());
+            }
+        }
+
+        // [package] section
+        if let Some(package) = parsed.section("package", &["namespace", "name", "version"])? {
+            config.package.namespace = package.string("namespace")?;
+            config.package.name = package.string("name")?;
+            config.package.version = package.string("version")?;
+        }
+
+        // [deprecation] section
+        if let Some(deprecation) = parsed.section("deprecation", &["since", "feature"])? {
+            config.deprecation.since = deprecation.string("since")?;
+            config.deprecation.feature = deprecation.string("feature")?;
+        }
+
+        // [cfg] section
+        if let Some(cfg) = parsed.section("cfg", &["features", "default-features", "cfg", "feature-gates"])? {
+            config.cfg.features = cfg.list("features")?;
+            config.cfg.default_features = cfg.boolean("default-features")?.unwrap_or(true);
+            config.cfg.options = cfg.list("cfg")?.iter().map(|o| parse_cfg_option(o)).collect();
+            config.cfg.feature_gates = cfg.boolean("feature-gates")?.unwrap_or(false);
+        }
+
+        // [worlds.<name>] sections
+        for (name, world) in parsed.entries("worlds")? {
+            let world = Section::new(format!("worlds.{}", name), world, &["includes", "imports", "exports"])?;
+            let world_config = WorldConfig {
+                includes: world.list("includes")?,
+                imports: world.list("imports")?,
+                exports: world.list("exports")?,
+            };
+            println!("  World {}: {:?}", name, world_config.statements());
+            config.worlds.insert(name.clone(), world_config);
+        }
+
+        Ok(config)
+    }
+}
+
+// A table of the configuration file, with the dotted path of its keys for error messages. Keys
+// it doesn't know are rejected rather than ignored, so a misspelt or misplaced key doesn't go
+// unnoticed.
+struct Section<'a> {
+    path: String,
+    table: &'a toml::map::Map<String, Value>,
+}
+
+impl<'a> Section<'a> {
+    fn new(path: String, value: &'a Value, known: &[&str]) -> Result<Self> {
+        let section = Section {
+            table: value.as_table().with_context(|| format!("{} must be a table", path))?,
+            path,
+        };
+        for key in section.table.keys() {
+            if !known.contains(&key.as_str()) {
+                anyhow::bail!(
+                    "unknown key {} in {}; expected one of {}",
+                    section.key_path(key),
+                    CONFIG_FILE_NAME,
+                    known.join(", ")
+                );
+            }
+        }
+        Ok(section)
+    }
+
+    // Dotted path of one of its keys, e.g. `cfg.features`
+    fn key_path(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    // The table under `key`, if present, with the keys it may hold
+    fn section(&self, key: &str, known: &[&str]) -> Result<Option<Section<'a>>> {
+        self.table.get(key).map(|value| Section::new(self.key_path(key), value, known)).transpose()
+    }
+
+    // The entries of the table under `key`, whose keys are names chosen by the user
+    fn entries(&self, key: &str) -> Result<Vec<(&'a String, &'a Value)>> {
+        let Some(value) = self.table.get(key) else {
+            return Ok(Vec::new());
+        };
+        let table = value.as_table().with_context(|| format!("{} must be a table", self.key_path(key)))?;
+        Ok(table.iter().collect())
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>> {
+        self.table
+            .get(key)
+            .map(|v| v.as_str().map(str::to_string).with_context(|| format!("{} must be a string", self.key_path(key))))
+            .transpose()
+    }
+
+    fn boolean(&self, key: &str) -> Result<Option<bool>> {
+        self.table
+            .get(key)
+            .map(|v| v.as_bool().with_context(|| format!("{} must be a boolean", self.key_path(key))))
+            .transpose()
+    }
+
+    fn list(&self, key: &str) -> Result<Vec<String>> {
+        let Some(list) = self.table.get(key) else {
+            return Ok(Vec::new());
+        };
+        list.as_array()
+            .and_then(|items| items.iter().map(|i| i.as_str().map(str::to_string)).collect())
+            .with_context(|| format!("{} must be a list of strings", self.key_path(key)))
+    }
+}
+
+// Parse a `[types.mappings."path::Type"]` entry
+fn parse_type_mapping(rust_path: &str, value: &Value) -> Result<TypeMapping> {
+    let entry = Section::new(
+        format!("types.mappings.\"{}\"", rust_path),
+        value,
+        &["wit", "rust", "name", "default", "dependency"],
+    )?;
+
+    let wit = entry.string("wit")?
+        .with_context(|| format!("types.mappings.\"{}\" is missing `wit`", rust_path))?;
+
+    let mut mapping = TypeMapping::alias(rust_path, &wit, None);
+    if let Some(name) = entry.string("name")? {
+        mapping.wit_name = name;
+    }
+    if let Some(caller_type) = entry.string("rust")? {
+        mapping.caller_type = caller_type;
+    }
+    mapping.default_value = entry.string("default")?;
+    mapping.dependency = entry.string("dependency")?;
+    Ok(mapping)
+}
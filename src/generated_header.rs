@@ -0,0 +1,178 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::config::{CfgConfig, CONFIG_FILE_NAME};
+
+// First line of the header opening every file hyper-bindgen generates
+const HEADER_NOTICE: &str = "// Generated by hyper-bindgen. DO NOT EDIT: change the Rust sources and run it again instead.";
+
+// 64-bit FNV-1a, which unlike std's hasher is stable across Rust releases
+fn fnv1a(bytes: &[u8], mut hash: u64) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+fn content_hash(body: &str) -> String {
+    format!("{:016x}", fnv1a(body.as_bytes(), FNV_OFFSET))
+}
+
+// Hash of everything the generated files are derived from: the configuration, including the
+// features and cfg options given on the command line, and the manifest and sources of each process
+pub fn input_hash(base_dir: &Path, projects: &[PathBuf], cfg: &CfgConfig) -> String {
+    let mut inputs = vec![base_dir.join(CONFIG_FILE_NAME)];
+    for project_path in projects {
+        inputs.push(project_path.join("Cargo.toml"));
+        inputs.extend(
+            WalkDir::new(project_path.join("src"))
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(Result::ok)
+                .map(|entry| entry.into_path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "rs")),
+        );
+    }
+
+    let mut hash = fnv1a(format!("{:?}", cfg).as_bytes(), FNV_OFFSET);
+    for path in inputs {
+        let Ok(content) = fs::read(&path) else {
+            continue;
+        };
+        let relative = path.strip_prefix(base_dir).unwrap_or(&path);
+        hash = fnv1a(relative.to_string_lossy().as_bytes(), hash);
+        hash = fnv1a(&content, hash);
+    }
+    format!("{:016x}", hash)
+}
+
+// Insert the generated-file header into `content` at byte offset `at`, the start of a line.
+// The content hash covers everything but the header.
+fn insert_header(content: &str, at: usize, input_hash: &str) -> String {
+    let (before, after) = content.split_at(at);
+    format!(
+        "{}{}\n// hyper-bindgen {}, input hash {}, content hash {}\n{}",
+        before,
+        HEADER_NOTICE,
+        env!("CARGO_PKG_VERSION"),
+        input_hash,
+        content_hash(&format!("{}{}", before, after)),
+        after
+    )
+}
+
+// Prefix a generated file with the header
+pub fn with_header(content: &str, input_hash: &str) -> String {
+    insert_header(content, 0, input_hash)
+}
+
+// Add the header to a generated WIT file after its `package` declaration, since wit-parser
+// would take comments before it for the package's documentation
+pub fn with_wit_header(content: &str, input_hash: &str) -> String {
+    let at = match content.split_once("\n\n") {
+        Some((decl, _)) if decl.starts_with("package ") => decl.len() + 2,
+        _ => 0,
+    };
+    insert_header(content, at, input_hash)
+}
+
+// Whether a generated file still matches the content hash in its header. Files without the
+// header aren't ones hyper-bindgen generated.
+fn check_header(content: &str) -> Option<bool> {
+    let start = if content.starts_with(HEADER_NOTICE) {
+        0
+    } else {
+        content.find(&format!("\n{}\n", HEADER_NOTICE))? + 1
+    };
+    let (before, rest) = content.split_at(start);
+    let (_, rest) = rest.split_once('\n')?;
+    let (details, after) = rest.split_once('\n')?;
+    let recorded = details.rsplit_once("content hash ")?.1.trim();
+    Some(recorded == content_hash(&format!("{}{}", before, after)))
+}
+
+// Fail if a generated file at `path` was edited since it was written, unless `force` is set
+pub fn ensure_unmodified(path: &Path, force: bool) -> Result<()> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+    match check_header(&content) {
+        Some(false) if force => {
+            println!("Overwriting edited generated file {} (--force)", path.display());
+            Ok(())
+        }
+        Some(false) => anyhow::bail!(
+            "Error: {} was edited by hand since hyper-bindgen generated it; move your changes \
+             into the Rust sources, or run with --force to discard them",
+            path.display()
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_verify_the_content_they_were_written_with() {
+        let generated = with_header("pub mod alpha {}\n", "0123456789abcdef");
+        assert!(generated.starts_with(HEADER_NOTICE));
+        assert!(generated.contains("input hash 0123456789abcdef"));
+        assert_eq!(check_header(&generated), Some(true));
+        assert_eq!(check_header(&generated.replace("alpha", "beta")), Some(false));
+        assert_eq!(check_header("pub mod alpha {}\n"), None);
+    }
+
+    #[test]
+    fn wit_headers_go_after_the_package_declaration() {
+        let generated = with_wit_header("package my:app;\n\ninterface alpha {}\n", "0123456789abcdef");
+        assert!(generated.starts_with(&format!("package my:app;\n\n{}\n", HEADER_NOTICE)));
+        assert_eq!(check_header(&generated), Some(true));
+        assert_eq!(check_header(&generated.replace("package my:app;", "package my:other;")), Some(false));
+    }
+
+    #[test]
+    fn edited_files_are_only_replaced_with_force() {
+        let dir = std::env::temp_dir().join(format!("hyper-bindgen-header-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lib.rs");
+
+        // Missing files and files without the header are free to write
+        assert!(ensure_unmodified(&path, false).is_ok());
+        fs::write(&path, "fn hand_written() {}\n").unwrap();
+        assert!(ensure_unmodified(&path, false).is_ok());
+
+        let generated = with_header("pub mod alpha {}\n", "0123456789abcdef");
+        fs::write(&path, &generated).unwrap();
+        assert!(ensure_unmodified(&path, false).is_ok());
+        fs::write(&path, format!("{}fn edit() {{}}\n", generated)).unwrap();
+        assert!(ensure_unmodified(&path, false).is_err());
+        assert!(ensure_unmodified(&path, true).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn input_hash_follows_the_sources_and_cfg() {
+        let dir = std::env::temp_dir().join(format!("hyper-bindgen-inputs-{}", std::process::id()));
+        let project = dir.join("alpha");
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::write(project.join("Cargo.toml"), "[package]\nname = \"alpha\"\n").unwrap();
+        fs::write(project.join("src").join("lib.rs"), "fn a() {}\n").unwrap();
+        let projects = [project.clone()];
+
+        let cfg = CfgConfig::default();
+        let hash = input_hash(&dir, &projects, &cfg);
+        assert_eq!(input_hash(&dir, &projects, &cfg), hash);
+        let with_feature = CfgConfig { features: vec!["admin".to_string()], ..CfgConfig::default() };
+        assert_ne!(input_hash(&dir, &projects, &with_feature), hash);
+        fs::write(project.join("src").join("lib.rs"), "fn b() {}\n").unwrap();
+        assert_ne!(input_hash(&dir, &projects, &cfg), hash);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
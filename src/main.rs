@@ -1,13 +1,60 @@
 use anyhow::Result;
+use clap::{Parser, Subcommand};
 
+mod cfg;
+mod config;
+mod generated_header;
+mod manifest;
+mod naming;
+mod serde_attrs;
 mod wit_generator;
+mod wit_package;
+mod wit_validator;
+mod world_file;
 mod caller_utils_generator;
 
+#[derive(Parser)]
+#[command(version, about = "Generate WIT files and a caller-utils crate from hyperprocess code")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Overwrite generated files even if they were edited by hand
+    #[arg(long, global = true)]
+    force: bool,
+    /// Features to evaluate #[cfg] against, on top of `[cfg] features`
+    #[arg(long, global = true, value_delimiter = ',')]
+    features: Vec<String>,
+    /// Don't enable the `default` feature
+    #[arg(long, global = true)]
+    no_default_features: bool,
+    /// Extra cfg option, as `name` or `name=value` (repeatable)
+    #[arg(long = "cfg", global = true, value_name = "OPTION")]
+    cfg_options: Vec<String>,
+    /// Keep handlers behind a single feature and gate them in the WIT and caller-utils
+    #[arg(long, global = true)]
+    feature_gates: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate the WIT files and the caller-utils crate (the default)
+    Generate,
+    /// Remove everything hyper-bindgen generated and undo its Cargo.toml edits
+    Clean,
+}
+
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     // Get the current working directory
     let cwd = std::env::current_dir()?;
     println!("Current working directory: {}", cwd.display());
     
+    if let Some(Command::Clean) = cli.command {
+        println!("\n=== Removing Generated Files ===");
+        return manifest::clean(&cwd);
+    }
+    
     // Create the api directory if it doesn't exist
     let api_dir = cwd.join("api");
     println!("API directory: {}", api_dir.display());
@@ -15,23 +62,38 @@ fn main() -> Result<()> {
     std::fs::create_dir_all(&api_dir)?;
     println!("Created or verified api directory");
     
+    // Load hyper-bindgen.toml if present
+    let mut config = config::Config::load(&cwd)?;
+    
+    // Command-line cfg settings add to those of hyper-bindgen.toml
+    config.cfg.features.extend(cli.features);
+    config.cfg.default_features &= !cli.no_default_features;
+    config.cfg.options.extend(cli.cfg_options.iter().map(|o| cfg::parse_cfg_option(o)));
+    config.cfg.feature_gates |= cli.feature_gates;
+    
+    // Files generated by earlier runs
+    let mut manifest = manifest::Manifest::load(&cwd)?;
+    
     // Step 1: Generate WIT files from Rust code
     println!("\n=== STEP 1: Generating WIT Files ===");
-    let (processed_projects, interfaces) = wit_generator::generate_wit_files(&cwd, &api_dir)?;
+    let (processed_projects, interfaces) = wit_generator::generate_wit_files(&cwd, &api_dir, &config, &mut manifest, cli.force)?;
     
     if processed_projects.is_empty() {
         println!("No relevant Rust projects found with hyperware:process metadata.");
+        manifest.save(&cwd)?;
         return Ok(());
     }
     
     // Step 2: Create caller-utils crate with stubs
     println!("\n=== STEP 2: Generating Caller Utils Crate ===");
     if !interfaces.is_empty() {
-        caller_utils_generator::create_caller_utils(&cwd, &api_dir, &processed_projects)?;
+        caller_utils_generator::create_caller_utils(&cwd, &api_dir, &processed_projects, &config, &mut manifest, cli.force)?;
     } else {
         println!("No interfaces found, skipping caller-utils creation");
     }
     
+    manifest.save(&cwd)?;
+    
     // Print summary
     println!("\n=== Summary ===");
     println!("- Processed {} Rust projects", processed_projects.len());
@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+use walkdir::WalkDir;
+
+use crate::caller_utils_generator::{remove_caller_utils_dependency, remove_from_workspace_cargo_toml};
+use crate::generated_header::ensure_unmodified;
+use crate::wit_generator::generated_interface_name;
+use crate::world_file::{parse_worlds, update_world_items};
+
+// Record of what hyper-bindgen generated, kept in the api directory
+pub const MANIFEST_FILE_NAME: &str = ".hyper-bindgen-manifest.toml";
+
+// Everything hyper-bindgen created or edited in the workspace, so later runs can remove what is
+// no longer generated and `hyper-bindgen clean` can undo it. Paths are relative to the
+// workspace root.
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    // Files written from scratch, e.g. interface files and the worlds created for processes
+    pub files: BTreeSet<PathBuf>,
+    // Existing WIT files whose worlds were edited, with their content before the first edit
+    pub edited_worlds: BTreeMap<PathBuf, String>,
+    // Directories created from scratch, e.g. the caller-utils crate
+    pub directories: BTreeSet<PathBuf>,
+    // Whether caller-utils was added to the workspace members
+    pub workspace_member: bool,
+    // Cargo.toml files the caller-utils dependency was added to
+    pub dependencies: BTreeSet<PathBuf>,
+}
+
+fn path_list(table: &Value, key: &str) -> Result<BTreeSet<PathBuf>> {
+    let Some(value) = table.get(key) else {
+        return Ok(BTreeSet::new());
+    };
+    let array = value.as_array().with_context(|| format!("`{}` must be an array", key))?;
+    array
+        .iter()
+        .map(|v| v.as_str().map(PathBuf::from).with_context(|| format!("`{}` must only hold strings", key)))
+        .collect()
+}
+
+fn path_contents(table: &Value, key: &str, base_dir: &Path, files: &BTreeSet<PathBuf>) -> Result<BTreeMap<PathBuf, String>> {
+    let Some(value) = table.get(key) else {
+        return Ok(BTreeMap::new());
+    };
+    // Manifests of earlier versions only list the paths
+    if value.is_array() {
+        return Ok(path_list(table, key)?
+            .into_iter()
+            .filter_map(|path| {
+                let original = without_generated_items(&fs::read_to_string(base_dir.join(&path)).ok()?, base_dir, files);
+                Some((path, original))
+            })
+            .collect());
+    }
+    let contents = value.as_table().with_context(|| format!("`{}` must be a table", key))?;
+    contents
+        .iter()
+        .map(|(path, v)| {
+            let content = v.as_str().with_context(|| format!("`{}` must only hold strings", key))?;
+            Ok((PathBuf::from(path), content.to_string()))
+        })
+        .collect()
+}
+
+fn path_array(paths: &BTreeSet<PathBuf>) -> Value {
+    Value::Array(
+        paths
+            .iter()
+            .map(|p| Value::String(p.to_string_lossy().replace('\\', "/")))
+            .collect(),
+    )
+}
+
+impl Manifest {
+    fn path(base_dir: &Path) -> PathBuf {
+        base_dir.join("api").join(MANIFEST_FILE_NAME)
+    }
+
+    // Load the manifest of earlier runs, or an empty one if there wasn't any
+    pub fn load(base_dir: &Path) -> Result<Self> {
+        let path = Manifest::path(base_dir);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+        println!("Loading manifest of generated files from {}", path.display());
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let table: Value = content
+            .parse()
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let files = path_list(&table, "files")?;
+        Ok(Manifest {
+            edited_worlds: path_contents(&table, "edited_worlds", base_dir, &files)?,
+            files,
+            directories: path_list(&table, "directories")?,
+            workspace_member: table.get("workspace_member").and_then(Value::as_bool).unwrap_or(false),
+            dependencies: path_list(&table, "dependencies")?,
+        })
+    }
+
+    pub fn save(&self, base_dir: &Path) -> Result<()> {
+        let path = Manifest::path(base_dir);
+        let mut table = toml::map::Map::new();
+        table.insert("files".to_string(), path_array(&self.files));
+        let edited_worlds = self
+            .edited_worlds
+            .iter()
+            .map(|(p, content)| (p.to_string_lossy().replace('\\', "/"), Value::String(content.clone())))
+            .collect();
+        table.insert("edited_worlds".to_string(), Value::Table(edited_worlds));
+        table.insert("directories".to_string(), path_array(&self.directories));
+        table.insert("workspace_member".to_string(), Value::Boolean(self.workspace_member));
+        table.insert("dependencies".to_string(), path_array(&self.dependencies));
+        let content = format!(
+            "# Written by hyper-bindgen to track the files it generated; `hyper-bindgen clean` removes them\n{}",
+            toml::to_string_pretty(&Value::Table(table)).context("Failed to serialize the manifest")?
+        );
+        fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    // Remove everything recorded as generated and undo the edits to existing files, leaving the
+    // manifest empty
+    pub fn remove_all(&mut self, base_dir: &Path) -> Result<()> {
+        // Edited worlds get back their content from before hyper-bindgen touched them, including
+        // their `package` line and `process-v1` include
+        for (world_file, original) in &self.edited_worlds {
+            let path = base_dir.join(world_file);
+            if !path.exists() {
+                continue;
+            }
+            println!("Restoring {}", path.display());
+            fs::write(&path, original).with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+
+        for file in &self.files {
+            let path = base_dir.join(file);
+            if path.exists() {
+                println!("Removing {}", path.display());
+                fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        for directory in &self.directories {
+            let path = base_dir.join(directory);
+            if path.exists() {
+                println!("Removing {}", path.display());
+                fs::remove_dir_all(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+
+        if self.workspace_member {
+            remove_from_workspace_cargo_toml(base_dir)?;
+        }
+        for cargo_toml in &self.dependencies {
+            remove_caller_utils_dependency(&base_dir.join(cargo_toml))?;
+        }
+        *self = Manifest::default();
+        Ok(())
+    }
+
+    // Fail if a generated file recorded here was edited by hand since it was written, unless
+    // `force` is set
+    pub fn ensure_unmodified(&self, base_dir: &Path, force: bool) -> Result<()> {
+        for file in &self.files {
+            ensure_unmodified(&base_dir.join(file), force)?;
+        }
+        for directory in &self.directories {
+            for entry in WalkDir::new(base_dir.join(directory)).into_iter().filter_map(Result::ok) {
+                if entry.file_type().is_file() {
+                    ensure_unmodified(entry.path(), force)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Path relative to the workspace root, as recorded in the manifest
+    pub fn relative(base_dir: &Path, path: &Path) -> PathBuf {
+        path.strip_prefix(base_dir).unwrap_or(path).to_path_buf()
+    }
+}
+
+// A world file without the imports and exports of the generated interfaces in `files`, the
+// closest to its original content a manifest without that content allows
+fn without_generated_items(content: &str, base_dir: &Path, files: &BTreeSet<PathBuf>) -> String {
+    let owned: HashSet<String> = files
+        .iter()
+        .filter_map(|file| fs::read_to_string(base_dir.join(file)).ok())
+        .filter_map(|content| generated_interface_name(&content))
+        .collect();
+    let mut content = content.to_string();
+    for world in parse_worlds(&content) {
+        for keyword in ["import", "export"] {
+            if let Some(updated) = update_world_items(&content, &world.name, keyword, &[], &owned) {
+                content = updated;
+            }
+        }
+    }
+    content
+}
+
+// Remove everything the manifest records as generated and undo the edits to existing files
+pub fn clean(base_dir: &Path) -> Result<()> {
+    let manifest_path = Manifest::path(base_dir);
+    if !manifest_path.exists() {
+        println!("No manifest found at {}, nothing to clean", manifest_path.display());
+        return Ok(());
+    }
+    let mut manifest = Manifest::load(base_dir)?;
+    manifest.remove_all(base_dir)?;
+
+    fs::remove_file(&manifest_path)
+        .with_context(|| format!("Failed to remove {}", manifest_path.display()))?;
+    println!("Removed all generated files");
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use anyhow::Result;
+
+use crate::config::{DigitMangling, NamingConfig, CONFIG_FILE_NAME};
+
+// Split a Rust or WIT name into its words, e.g. `HTTPServer2_config` -> HTTP, Server2, config.
+// A new word starts at `_` or `-`, at a lowercase or digit to uppercase change, and before the
+// last capital of an acronym followed by lowercase letters.
+fn split_words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in s.split(['_', '-']) {
+        let chars: Vec<char> = part.chars().collect();
+        let mut word = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            if i > 0 && c.is_uppercase() {
+                let prev = chars[i - 1];
+                let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+                if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_is_lower) {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            word.push(c);
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+    words
+}
+
+// Convert a Rust name to lowercase kebab-case, e.g. `HTTP_Config` -> `http-config`
+pub fn to_kebab_case(s: &str) -> String {
+    split_words(s)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// Convert kebab-case to snake_case
+pub fn to_snake_case(s: &str) -> String {
+    s.replace('-', "_")
+}
+
+// Convert kebab-case to PascalCase, the way WIT bindings name types and variants
+pub fn to_pascal_case(s: &str) -> String {
+    let parts = s.split('-');
+    let mut result = String::new();
+    
+    for part in parts {
+        if !part.is_empty() {
+            let mut chars = part.chars();
+            if let Some(first_char) = chars.next() {
+                result.push(first_char.to_uppercase().next().unwrap());
+                result.extend(chars);
+            }
+        }
+    }
+    
+    result
+}
+
+// Request variant the hyperprocess macro generates for a handler method, which capitalizes
+// each `_`-separated part and keeps the rest as written, e.g. `get_URL` -> `GetURL`
+pub fn method_variant_name(method_name: &str) -> String {
+    method_name
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+// Rust keywords that can't be used as plain identifiers in the generated stubs
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro", "override",
+    "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+// Make a snake_case name usable as a Rust identifier the way wit-bindgen does, e.g. `type` -> `type_`
+pub fn to_rust_identifier(name: String) -> String {
+    if RUST_KEYWORDS.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else {
+        name
+    }
+}
+
+// WIT keywords, which have to be escaped with `%` when used as identifiers
+const WIT_KEYWORDS: &[&str] = &[
+    "as", "async", "bool", "borrow", "char", "constructor", "enum", "error-context", "export",
+    "f32", "f64", "flags", "float32", "float64", "from", "func", "future", "import", "include",
+    "interface", "list", "option", "own", "package", "record", "resource", "result", "s16", "s32",
+    "s64", "s8", "static", "stream", "string", "tuple", "type", "u16", "u32", "u64", "u8", "use",
+    "variant", "with", "world",
+];
+
+// Digits spelled out, for mangling name segments that start with a digit
+const DIGIT_WORDS: [&str; 10] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+
+// Check a name against the WIT identifier grammar: `-`-separated segments, each starting
+// with a letter and either all lowercase or all uppercase
+pub fn check_wit_identifier(name: &str) -> std::result::Result<(), String> {
+    for segment in name.split('-') {
+        let first = match segment.chars().next() {
+            Some(first) => first,
+            None => return Err("it has an empty segment".to_string()),
+        };
+        if !first.is_ascii_alphabetic() {
+            return Err(format!("segment '{}' doesn't start with a letter", segment));
+        }
+        let upper = first.is_ascii_uppercase();
+        let valid = segment.chars().all(|c| {
+            c.is_ascii_digit() || (upper && c.is_ascii_uppercase()) || (!upper && c.is_ascii_lowercase())
+        });
+        if !valid {
+            return Err(format!("segment '{}' mixes cases or contains invalid characters", segment));
+        }
+    }
+    Ok(())
+}
+
+// Escape a WIT identifier that is also a keyword, e.g. `type` -> `%type`
+pub fn escape_keyword(name: &str) -> String {
+    if WIT_KEYWORDS.contains(&name) {
+        format!("%{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// Strip the keyword escape from a WIT identifier, e.g. to build a longer name from it
+pub fn unescape(name: &str) -> &str {
+    name.strip_prefix('%').unwrap_or(name)
+}
+
+// Convert a Rust (or serde) name into a valid WIT identifier, applying configured renames,
+// mangling segments that start with a digit and escaping keywords
+pub fn wit_identifier(name: &str, kind: &str, naming: &NamingConfig) -> Result<String> {
+    // Raw identifiers like `r#type` name the same thing as `type`
+    let name = name.strip_prefix("r#").unwrap_or(name);
+
+    if let Some(renamed) = naming.renames.get(name) {
+        if let Err(reason) = check_wit_identifier(unescape(renamed)) {
+            anyhow::bail!(
+                "Error: {} '{}' is renamed to '{}' in {}, which is not a valid WIT identifier: {}",
+                kind, name, renamed, CONFIG_FILE_NAME, reason
+            );
+        }
+        return Ok(escape_keyword(unescape(renamed)));
+    }
+
+    if let Some(c) = name.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '_' && *c != '-') {
+        anyhow::bail!(
+            "Error: {} name '{}' contains '{}', which can't be used in a WIT identifier; \
+             give it a WIT name under [naming.renames] in {}",
+            kind, name, c, CONFIG_FILE_NAME
+        );
+    }
+
+    // Empty segments from leading, trailing or doubled underscores are dropped
+    let kebab = to_kebab_case(name);
+    let mut segments: Vec<String> = Vec::new();
+    for segment in kebab.split('-').filter(|segment| !segment.is_empty()) {
+        if !segment.starts_with(|c: char| c.is_ascii_digit()) {
+            segments.push(segment.to_string());
+            continue;
+        }
+
+        match naming.digits {
+            DigitMangling::Merge if !segments.is_empty() => {
+                segments.last_mut().unwrap().push_str(segment);
+            }
+            DigitMangling::Error => anyhow::bail!(
+                "Error: {} name '{}' has a segment '{}' starting with a digit, which WIT doesn't allow; \
+                 set `digits = \"merge\"` or `digits = \"spell\"` under [naming], or give it a WIT name \
+                 under [naming.renames] in {}",
+                kind, name, segment, CONFIG_FILE_NAME
+            ),
+            // Spelling is also the fallback when there is no previous segment to merge into
+            _ => {
+                let digits_end = segment
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(segment.len());
+                for digit in segment[..digits_end].chars() {
+                    let digit = digit.to_digit(10).unwrap() as usize;
+                    segments.push(DIGIT_WORDS[digit].to_string());
+                }
+                if digits_end < segment.len() {
+                    segments.push(segment[digits_end..].to_string());
+                }
+            }
+        }
+    }
+
+    if segments.is_empty() {
+        anyhow::bail!("Error: {} name '{}' has no characters usable in a WIT identifier", kind, name);
+    }
+
+    let identifier = segments.join("-");
+    if identifier != kebab {
+        println!("    Mangled {} name '{}' to '{}'", kind.to_lowercase(), name, identifier);
+    }
+    Ok(escape_keyword(&identifier))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naming(digits: DigitMangling) -> NamingConfig {
+        NamingConfig { digits, ..NamingConfig::default() }
+    }
+
+    #[test]
+    fn words_split_at_separators_case_changes_and_acronyms() {
+        assert_eq!(split_words("HTTPServer2_config"), ["HTTP", "Server2", "config"]);
+        assert_eq!(split_words("getURL"), ["get", "URL"]);
+        assert_eq!(split_words("sha256Digest"), ["sha256", "Digest"]);
+        assert_eq!(split_words("token-info"), ["token", "info"]);
+        assert_eq!(split_words("__private"), ["private"]);
+    }
+
+    #[test]
+    fn conversions_round_trip_through_wit() {
+        assert_eq!(to_kebab_case("HTTP_Config"), "http-config");
+        assert_eq!(to_kebab_case("HTTPConfig"), "http-config");
+        assert_eq!(to_pascal_case(&to_kebab_case("TokenInfo")), "TokenInfo");
+        assert_eq!(to_snake_case(&to_kebab_case("display_name")), "display_name");
+        assert_eq!(method_variant_name("get_URL"), "GetURL");
+        assert_eq!(to_rust_identifier("type".to_string()), "type_");
+        assert_eq!(to_rust_identifier("kind".to_string()), "kind");
+    }
+
+    #[test]
+    fn keywords_are_escaped_with_a_percent_sign() {
+        assert_eq!(escape_keyword("type"), "%type");
+        assert_eq!(escape_keyword("stream"), "%stream");
+        assert_eq!(escape_keyword("kind"), "kind");
+        assert_eq!(unescape("%type"), "type");
+        assert_eq!(wit_identifier("r#type", "Field", &NamingConfig::default()).unwrap(), "%type");
+        assert_eq!(wit_identifier("List", "Type", &NamingConfig::default()).unwrap(), "%list");
+    }
+
+    #[test]
+    fn segments_starting_with_a_digit_are_mangled() {
+        assert_eq!(wit_identifier("sha256", "Field", &naming(DigitMangling::Merge)).unwrap(), "sha256");
+        assert_eq!(wit_identifier("enable_2fa", "Field", &naming(DigitMangling::Merge)).unwrap(), "enable2fa");
+        assert_eq!(wit_identifier("enable_2fa", "Field", &naming(DigitMangling::Spell)).unwrap(), "enable-two-fa");
+        assert_eq!(wit_identifier("_3d", "Field", &naming(DigitMangling::Merge)).unwrap(), "three-d");
+        assert!(wit_identifier("enable_2fa", "Field", &naming(DigitMangling::Error)).is_err());
+    }
+
+    #[test]
+    fn renames_are_checked() {
+        let mut config = NamingConfig::default();
+        config.renames.insert("Sha256Digest".to_string(), "digest".to_string());
+        config.renames.insert("Bad".to_string(), "Not_Valid".to_string());
+        assert_eq!(wit_identifier("Sha256Digest", "Type", &config).unwrap(), "digest");
+        assert!(wit_identifier("Bad", "Type", &config).is_err());
+        assert!(check_wit_identifier("http-Config").is_err());
+        assert!(check_wit_identifier("HTTP-config").is_ok());
+    }
+}
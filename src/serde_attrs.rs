@@ -0,0 +1,257 @@
+use anyhow::Result;
+use syn::meta::ParseNestedMeta;
+use syn::{Attribute, LitStr, Token};
+
+// Serde attributes that affect the JSON wire format of a container, field or variant
+#[derive(Debug, Default)]
+pub struct SerdeAttrs {
+    // #[serde(rename = "...")], using the serialize name if they differ
+    pub rename: Option<String>,
+    // #[serde(rename_all = "...")]
+    pub rename_all: Option<String>,
+    // #[serde(rename_all_fields = "...")] on enums, applied to struct-like variants
+    pub rename_all_fields: Option<String>,
+    // #[serde(skip)] or #[serde(skip_serializing)] - never sent
+    pub skip_serializing: bool,
+    // #[serde(skip)] or #[serde(skip_deserializing)] - never read, filled in with its default
+    pub skip_deserializing: bool,
+    // #[serde(skip_serializing_if = "...")] - sent only for some values
+    pub skip_serializing_if: bool,
+    // #[serde(flatten)]
+    pub flatten: bool,
+    // #[serde(default)] - a missing key is filled in, the field itself is still serialized
+    pub default: bool,
+    // #[serde(transparent)]
+    pub transparent: bool,
+    // #[serde(tag = "...")]
+    pub tag: Option<String>,
+    // #[serde(content = "...")]
+    pub content: Option<String>,
+    // #[serde(untagged)]
+    pub untagged: bool,
+    // #[serde(with / serialize_with / from / into / ...)] - custom (de)serialization
+    pub custom: Option<String>,
+}
+
+impl SerdeAttrs {
+    // Parse all #[serde(...)] attributes of an item, field or variant
+    pub fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut serde = SerdeAttrs::default();
+
+        for attr in attrs {
+            if !attr.path().is_ident("serde") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                let key = meta
+                    .path
+                    .get_ident()
+                    .map(|ident| ident.to_string())
+                    .unwrap_or_default();
+
+                match key.as_str() {
+                    "rename" => serde.rename = Some(parse_name(&meta)?),
+                    "rename_all" => serde.rename_all = Some(parse_name(&meta)?),
+                    "rename_all_fields" => serde.rename_all_fields = Some(parse_name(&meta)?),
+                    "skip" => {
+                        serde.skip_serializing = true;
+                        serde.skip_deserializing = true;
+                    }
+                    "skip_serializing" => serde.skip_serializing = true,
+                    "skip_deserializing" => serde.skip_deserializing = true,
+                    // `skip_serializing_if` only leaves out some values
+                    "skip_serializing_if" => {
+                        serde.skip_serializing_if = true;
+                        skip_value(&meta)?;
+                    }
+                    "flatten" => serde.flatten = true,
+                    "transparent" => serde.transparent = true,
+                    "untagged" => serde.untagged = true,
+                    "tag" => serde.tag = Some(meta.value()?.parse::<LitStr>()?.value()),
+                    "content" => serde.content = Some(meta.value()?.parse::<LitStr>()?.value()),
+                    "default" => {
+                        serde.default = true;
+                        skip_value(&meta)?;
+                    }
+                    "with" | "serialize_with" | "deserialize_with" | "from" | "try_from" | "into"
+                    | "remote" => {
+                        serde.custom = Some(key.clone());
+                        skip_value(&meta)?;
+                    }
+                    // Attributes like `alias`, `bound` or `deny_unknown_fields` don't change
+                    // what the serialized form looks like
+                    _ => skip_value(&meta)?,
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(serde)
+    }
+
+    // Skipped in both directions, so never part of the JSON
+    pub fn skip(&self) -> bool {
+        self.skip_serializing && self.skip_deserializing
+    }
+
+    // Present in the JSON only some of the time: skipped in one direction, only sent for some
+    // values, or filled in when missing
+    pub fn optional(&self) -> bool {
+        self.default || self.skip_serializing || self.skip_deserializing || self.skip_serializing_if
+    }
+}
+
+// Parse `name = "..."` or `name(serialize = "...", deserialize = "...")`
+fn parse_name(meta: &ParseNestedMeta) -> syn::Result<String> {
+    if meta.input.peek(Token![=]) {
+        return Ok(meta.value()?.parse::<LitStr>()?.value());
+    }
+
+    let mut name = None;
+    meta.parse_nested_meta(|inner| {
+        let value = inner.value()?.parse::<LitStr>()?.value();
+        // Prefer the serialize name, it's what ends up on the wire in responses
+        if inner.path.is_ident("serialize") || name.is_none() {
+            name = Some(value);
+        }
+        Ok(())
+    })?;
+    name.ok_or_else(|| meta.error("expected a serialize or deserialize name"))
+}
+
+// Consume the value of an attribute we don't interpret, if it has one
+fn skip_value(meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|inner| skip_value(&inner))?;
+    }
+    Ok(())
+}
+
+// Apply a serde `rename_all` rule to a variant name (PascalCase in Rust)
+pub fn rename_variant(rule: &str, variant: &str) -> Result<String> {
+    let snake = || {
+        let mut snake = String::new();
+        for (i, c) in variant.char_indices() {
+            if i > 0 && c.is_uppercase() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    };
+
+    Ok(match rule {
+        "lowercase" => variant.to_ascii_lowercase(),
+        "UPPERCASE" => variant.to_ascii_uppercase(),
+        "PascalCase" => variant.to_string(),
+        "camelCase" => {
+            let mut chars = variant.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        }
+        "snake_case" => snake(),
+        "SCREAMING_SNAKE_CASE" => snake().to_ascii_uppercase(),
+        "kebab-case" => snake().replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => snake().to_ascii_uppercase().replace('_', "-"),
+        _ => anyhow::bail!("Error: unknown serde rename rule '{}'", rule),
+    })
+}
+
+// Apply a serde `rename_all` rule to a field name (snake_case in Rust)
+pub fn rename_field(rule: &str, field: &str) -> Result<String> {
+    let pascal = || {
+        field
+            .split('_')
+            .map(|part| {
+                let mut chars = part.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                    None => String::new(),
+                }
+            })
+            .collect::<String>()
+    };
+
+    Ok(match rule {
+        "lowercase" | "snake_case" => field.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        }
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.to_ascii_uppercase().replace('_', "-"),
+        _ => anyhow::bail!("Error: unknown serde rename rule '{}'", rule),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(attrs: &str) -> SerdeAttrs {
+        let item: syn::ItemStruct = syn::parse_str(&format!("{} struct S;", attrs)).unwrap();
+        SerdeAttrs::parse(&item.attrs).unwrap()
+    }
+
+    #[test]
+    fn renames_prefer_the_serialize_name() {
+        assert_eq!(parse(r#"#[serde(rename = "userName")]"#).rename.as_deref(), Some("userName"));
+        let serde = parse(r#"#[serde(rename(deserialize = "in", serialize = "out"))]"#);
+        assert_eq!(serde.rename.as_deref(), Some("out"));
+        let serde = parse(r#"#[serde(rename_all = "camelCase", rename_all_fields = "kebab-case")]"#);
+        assert_eq!(serde.rename_all.as_deref(), Some("camelCase"));
+        assert_eq!(serde.rename_all_fields.as_deref(), Some("kebab-case"));
+    }
+
+    #[test]
+    fn skips_in_one_direction_keep_the_field_but_make_it_optional() {
+        let skip = parse("#[serde(skip)]");
+        assert!(skip.skip());
+        let one_way = parse("#[serde(skip_serializing)]");
+        assert!(!one_way.skip() && one_way.optional());
+        let both = parse("#[serde(skip_serializing)] #[serde(skip_deserializing)]");
+        assert!(both.skip());
+        let conditional = parse(r#"#[serde(skip_serializing_if = "Option::is_none")]"#);
+        assert!(!conditional.skip() && conditional.optional());
+    }
+
+    #[test]
+    fn defaults_and_flatten_are_recorded() {
+        assert!(parse("#[serde(default)]").optional());
+        assert!(parse(r#"#[serde(default = "default_limit")]"#).default);
+        assert!(!parse("#[serde(flatten)]").optional());
+        assert!(parse("#[serde(flatten)]").flatten);
+        assert!(parse("#[serde(transparent)]").transparent);
+    }
+
+    #[test]
+    fn other_attributes_are_skipped() {
+        let serde = parse(r#"#[derive(Debug)] #[serde(deny_unknown_fields, alias = "x", bound(serialize = "T: Clone"))]"#);
+        assert!(serde.rename.is_none() && !serde.optional() && serde.custom.is_none());
+        assert_eq!(parse(r#"#[serde(with = "json")]"#).custom.as_deref(), Some("with"));
+        assert!(parse("#[serde(untagged)]").untagged);
+        assert_eq!(parse(r#"#[serde(tag = "type")]"#).tag.as_deref(), Some("type"));
+    }
+
+    #[test]
+    fn rename_rules() {
+        assert_eq!(rename_variant("snake_case", "GetURL").unwrap(), "get_u_r_l");
+        assert_eq!(rename_variant("camelCase", "TokenInfo").unwrap(), "tokenInfo");
+        assert_eq!(rename_variant("SCREAMING-KEBAB-CASE", "TokenInfo").unwrap(), "TOKEN-INFO");
+        assert_eq!(rename_field("camelCase", "display_name").unwrap(), "displayName");
+        assert_eq!(rename_field("PascalCase", "display_name").unwrap(), "DisplayName");
+        assert_eq!(rename_field("kebab-case", "display_name").unwrap(), "display-name");
+        assert!(rename_field("Title Case", "display_name").is_err());
+    }
+}
@@ -1,56 +1,24 @@
 use anyhow::{Context, Result};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
-use syn::{self, Attribute, ImplItem, Item, Type};
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::visit_mut::{self, VisitMut};
+use syn::{self, braced, Attribute, ImplItem, Item, Token, Type};
 use walkdir::WalkDir;
 use toml::Value;
 
-// Helper functions for naming conventions
-fn to_kebab_case(s: &str) -> String {
-    // First, handle the case where the input has underscores
-    if s.contains('_') {
-        return s.replace('_', "-");
-    }
-    
-    let mut result = String::with_capacity(s.len() + 5); // Extra capacity for hyphens
-    let chars: Vec<char> = s.chars().collect();
-    
-    for (i, &c) in chars.iter().enumerate() {
-        if c.is_uppercase() {
-            // Add hyphen if:
-            // 1. Not the first character
-            // 2. Previous character is lowercase
-            // 3. Or next character is lowercase (to handle acronyms like HTML)
-            if i > 0 && 
-               (chars[i-1].is_lowercase() ||
-                (i < chars.len() - 1 && chars[i+1].is_lowercase()))
-            {
-                result.push('-');
-            }
-            result.push(c.to_lowercase().next().unwrap());
-        } else {
-            result.push(c);
-        }
-    }
-    
-    result
-}
-
-// Validates a name doesn't contain numbers or "stream"
-fn validate_name(name: &str, kind: &str) -> Result<()> {
-    // Check for numbers
-    if name.chars().any(|c| c.is_digit(10)) {
-        anyhow::bail!("Error: {} name '{}' contains numbers, which is not allowed", kind, name);
-    }
-    
-    // Check for "stream"
-    if name.to_lowercase().contains("stream") {
-        anyhow::bail!("Error: {} name '{}' contains 'stream', which is not allowed", kind, name);
-    }
-    
-    Ok(())
-}
+use crate::config::{Config, DeprecationConfig, NamingConfig, TypeMapping, WitMapping, CONFIG_FILE_NAME};
+use crate::naming::{escape_keyword, to_pascal_case, to_rust_identifier, to_snake_case, unescape, wit_identifier};
+use crate::serde_attrs::{self, SerdeAttrs};
+use crate::cfg::{apply_cfg, feature_gate, ActiveCfg};
+use crate::generated_header::{ensure_unmodified, input_hash, with_wit_header};
+use crate::manifest::Manifest;
+use crate::wit_package::{hyperware_dep, resolve_package, set_package_decl, PackageId};
+use crate::wit_validator::{diagnostic_location, enclosing_item, validate_package};
+use crate::world_file::{ensure_world_statements, parse_worlds, replace_include, update_world_items};
 
 // Remove "State" suffix from a name
 fn remove_state_suffix(name: &str) -> String {
@@ -61,6 +29,15 @@ fn remove_state_suffix(name: &str) -> String {
     name.to_string()
 }
 
+// WIT name of the interface for a hyperprocess impl type. A rename of the full impl type name
+// takes precedence, so two impls like `App` and `AppState` can be told apart.
+fn interface_wit_name(impl_name: &str, naming: &NamingConfig) -> Result<String> {
+    if naming.renames.contains_key(impl_name) {
+        return wit_identifier(impl_name, "Interface", naming);
+    }
+    wit_identifier(&remove_state_suffix(impl_name), "Interface", naming)
+}
+
 // Extract wit_world from the #[hyperprocess] attribute using the format in the debug representation
 fn extract_wit_world(attrs: &[Attribute]) -> Result<String> {
     for attr in attrs {
@@ -91,8 +68,404 @@ fn extract_wit_world(attrs: &[Attribute]) -> Result<String> {
     anyhow::bail!("wit_world not found in hyperprocess attribute")
 }
 
+// hyperware_process_lib types that map to records of the `standard` interface in hyperware.wit
+const STANDARD_TYPES: &[(&str, &str)] = &[
+    ("Address", "address"),
+    ("ProcessId", "process-id"),
+    ("PackageId", "package-id"),
+    ("Capability", "capability"),
+    ("LazyLoadBlob", "lazy-load-blob"),
+];
+
+// Whether a WIT name is a type of the `standard` interface
+fn is_standard_type(wit_name: &str) -> bool {
+    STANDARD_TYPES.iter().any(|(_, standard)| *standard == wit_name)
+}
+
+// Look up the `standard` WIT type for a hyperware_process_lib type used at `type_path`.
+// A type of the same name declared in the file shadows the library type unless the path
+// names hyperware_process_lib explicitly.
+fn standard_wit_type(type_path: &syn::TypePath, ctx: &TypeContext) -> Option<&'static str> {
+    let segments = &type_path.path.segments;
+    let type_name = segments.last()?.ident.to_string();
+    let (_, wit_name) = STANDARD_TYPES.iter().find(|(rust_name, _)| *rust_name == type_name)?;
+
+    let qualified = segments.len() > 1 && segments[0].ident == "hyperware_process_lib";
+    if ctx.declared.contains(&type_name) && !qualified {
+        return None;
+    }
+    Some(wit_name)
+}
+
+// Look up a configured or built-in mapping for the type at `type_path`. As with the
+// standard types, a type of the same name declared in the file takes precedence over
+// an unqualified path.
+fn mapped_type<'a>(type_path: &syn::TypePath, ctx: &'a TypeContext) -> Option<&'a TypeMapping> {
+    let segments: Vec<String> = type_path
+        .path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    if segments.len() == 1 && ctx.declared.contains(&segments[0]) {
+        return None;
+    }
+    ctx.type_mappings.iter().find(|mapping| mapping.matches(&segments))
+}
+
+// Comment prefix marking a WIT type generated from a type mapping, recording the mapped Rust
+// path, e.g. `// Mapped from: std::time::Duration`. Only marked types map back to the Rust type.
+pub const MAPPING_COMMENT: &str = "Mapped from: ";
+
+// Render the WIT definition for a mapped type
+fn mapping_to_wit(mapping: &TypeMapping) -> String {
+    let definition = match &mapping.wit {
+        WitMapping::Alias(wit_type) => format!("    type {} = {};", mapping.wit_name, wit_type),
+        WitMapping::Record(fields) => {
+            let field_strings: Vec<String> = fields
+                .iter()
+                .map(|(name, ty)| format!("        {}: {}", name, ty))
+                .collect();
+            record_to_wit(&mapping.wit_name, &field_strings)
+        }
+    };
+    format!("    // {}{}\n{}", MAPPING_COMMENT, mapping.rust_path, definition)
+}
+
+// Source location of a span in a file, e.g. `proc-a/src/lib.rs:12:12`
+fn span_location(file: &str, span: proc_macro2::Span) -> String {
+    let start = span.start();
+    format!("{}:{}:{}", file, start.line, start.column + 1)
+}
+
+// Remember which Rust item a WIT name was generated from, failing if another item already has it
+fn claim_wit_name(
+    claimed: &mut HashMap<String, (String, String)>,
+    wit_name: &str,
+    kind: &str,
+    rust_name: &str,
+    location: &str,
+) -> Result<()> {
+    if let Some((other_name, other_location)) = claimed.get(wit_name) {
+        anyhow::bail!(
+            "Error: {} '{}' at {} and '{}' at {} both map to the WIT name '{}'; \
+             give one of them a different WIT name under [naming.renames] in {}",
+            kind, other_name, other_location, rust_name, location, wit_name, CONFIG_FILE_NAME
+        );
+    }
+    claimed.insert(wit_name.to_string(), (rust_name.to_string(), location.to_string()));
+    Ok(())
+}
+
+// A rendered WIT type definition and the WIT types it references
+#[derive(Debug, Clone, Default)]
+struct TypeDef {
+    wit: String,
+    references: BTreeSet<String>,
+}
+
+impl TypeDef {
+    fn new(wit: String, references: HashSet<String>) -> Self {
+        TypeDef { wit, references: references.into_iter().collect() }
+    }
+}
+
+// Type information shared while generating a single interface
+struct TypeContext {
+    // Type aliases declared in the file, keyed by their Rust name
+    aliases: HashMap<String, syn::ItemType>,
+    // Whether aliases are inlined at use sites or emitted as WIT `type` definitions
+    inline_aliases: bool,
+    // Aliases currently being expanded, to avoid looping on self-referential aliases
+    expanding: Vec<String>,
+    // Generic structs and enums declared in the file, keyed by their Rust name
+    generic_items: HashMap<String, Item>,
+    // All structs declared in the file, used to inline #[serde(flatten)] fields
+    structs: HashMap<String, syn::ItemStruct>,
+    // WIT definitions for instantiated generic aliases and types, keyed by their kebab name
+    instantiations: BTreeMap<String, TypeDef>,
+    // Records for struct-like enum variants: (name, definition, Rust variant and its location)
+    variant_records: Vec<(String, TypeDef, String)>,
+    // Errors for types that couldn't be converted, reported only if the type is used
+    type_errors: HashMap<String, anyhow::Error>,
+    // Names of all types declared in the file, which shadow library and mapped types
+    declared: HashSet<String>,
+    // Built-in and configured mappings for types not declared in the project
+    type_mappings: Vec<TypeMapping>,
+    // WIT definitions of mapped types used so far, keyed by their kebab name
+    mapped_types: BTreeMap<String, String>,
+    // Template for naming records generated for struct-like enum variants
+    variant_record_name: String,
+    // File the types are declared in, for reporting source locations
+    source_file: String,
+    // Where each WIT type comes from in the source, keyed by its kebab name
+    type_sources: HashMap<String, String>,
+    // References (container, type) that close a recursive cycle and are encoded as the fallback
+    recursive_fields: HashSet<(String, String)>,
+    // WIT type used in place of a field that closes a recursive cycle, if configured
+    recursive_fallback: Option<String>,
+    // How Rust names are turned into WIT identifiers
+    naming: NamingConfig,
+    // Feature gates of deprecated items
+    deprecation: DeprecationConfig,
+}
+
+impl TypeContext {
+    fn new(ast: &syn::File, source_file: &Path, config: &Config) -> Self {
+        let mut aliases = HashMap::new();
+        let mut generic_items = HashMap::new();
+        let mut structs = HashMap::new();
+        let mut declared = HashSet::new();
+        for item in &ast.items {
+            match item {
+                Item::Struct(item_struct) => {
+                    structs.insert(item_struct.ident.to_string(), item_struct.clone());
+                    declared.insert(item_struct.ident.to_string());
+                }
+                Item::Enum(item_enum) => {
+                    declared.insert(item_enum.ident.to_string());
+                }
+                Item::Type(item_type) => {
+                    declared.insert(item_type.ident.to_string());
+                }
+                _ => {}
+            }
+            match item {
+                Item::Type(item_type) => {
+                    println!("  Found type alias: {}", item_type.ident);
+                    aliases.insert(item_type.ident.to_string(), item_type.clone());
+                }
+                Item::Struct(item_struct) if item_struct.generics.type_params().next().is_some() => {
+                    println!("  Found generic struct: {}", item_struct.ident);
+                    generic_items.insert(item_struct.ident.to_string(), item.clone());
+                }
+                Item::Enum(item_enum) if item_enum.generics.type_params().next().is_some() => {
+                    println!("  Found generic enum: {}", item_enum.ident);
+                    generic_items.insert(item_enum.ident.to_string(), item.clone());
+                }
+                _ => {}
+            }
+        }
+
+        TypeContext {
+            aliases,
+            inline_aliases: config.inline_type_aliases,
+            expanding: Vec::new(),
+            generic_items,
+            structs,
+            instantiations: BTreeMap::new(),
+            variant_records: Vec::new(),
+            type_errors: HashMap::new(),
+            declared,
+            type_mappings: config.type_mappings.clone(),
+            mapped_types: BTreeMap::new(),
+            variant_record_name: config.variant_record_name.clone(),
+            source_file: source_file.display().to_string(),
+            type_sources: HashMap::new(),
+            recursive_fields: HashSet::new(),
+            recursive_fallback: config.recursive_fallback.clone(),
+            naming: config.naming.clone(),
+            deprecation: config.deprecation.clone(),
+        }
+    }
+
+    // Source location of a span, e.g. `proc-a/src/lib.rs:12:12`
+    fn location(&self, span: proc_macro2::Span) -> String {
+        span_location(&self.source_file, span)
+    }
+
+    // Name of the auxiliary record for a struct-like variant, e.g. `event-transfer`
+    fn variant_record_name(&self, enum_name: &str, variant_name: &str) -> String {
+        let name = self.variant_record_name
+            .replace("{enum}", unescape(enum_name))
+            .replace("{variant}", unescape(variant_name));
+        escape_keyword(&name)
+    }
+}
+
+// Replaces generic type parameters with concrete types
+struct GenericSubstitution<'a> {
+    params: &'a HashMap<String, Type>,
+}
+
+impl VisitMut for GenericSubstitution<'_> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if let Type::Path(type_path) = ty {
+            if type_path.qself.is_none() && type_path.path.segments.len() == 1 {
+                let segment = &type_path.path.segments[0];
+                if segment.arguments.is_empty() {
+                    if let Some(concrete) = self.params.get(&segment.ident.to_string()) {
+                        *ty = concrete.clone();
+                        return;
+                    }
+                }
+            }
+        }
+        visit_mut::visit_type_mut(self, ty);
+    }
+}
+
+// Map the generic parameters of `generics` to the type arguments of a use site
+fn generic_params(
+    generics: &syn::Generics,
+    arguments: &syn::PathArguments,
+    type_name: &str,
+) -> Result<HashMap<String, Type>> {
+    let params: Vec<String> = generics.type_params().map(|p| p.ident.to_string()).collect();
+    let args: Vec<Type> = match arguments {
+        syn::PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(ty.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    if params.len() != args.len() {
+        anyhow::bail!(
+            "Error: type '{}' expects {} generic argument(s) but {} were given",
+            type_name,
+            params.len(),
+            args.len()
+        );
+    }
+
+    Ok(params.into_iter().zip(args).collect())
+}
+
+// Substitute the generic parameters of `generics` with the type arguments of a use site
+fn substitute_generics(
+    target: &Type,
+    generics: &syn::Generics,
+    arguments: &syn::PathArguments,
+    type_name: &str,
+) -> Result<Type> {
+    let params = generic_params(generics, arguments, type_name)?;
+    let mut substituted = target.clone();
+    GenericSubstitution { params: &params }.visit_type_mut(&mut substituted);
+    Ok(substituted)
+}
+
+// Name of a generic instantiation, e.g. `page-order` for `Page<Order>`
+fn instance_name(
+    kebab_name: &str,
+    arguments: &syn::PathArguments,
+    ctx: &mut TypeContext,
+) -> Result<String> {
+    // The instantiated definition references the arguments, not the name
+    let mut used_types = HashSet::new();
+    let mut arg_fragments = Vec::new();
+    if let syn::PathArguments::AngleBracketed(args) = arguments {
+        for arg in &args.args {
+            if let syn::GenericArgument::Type(arg_ty) = arg {
+                let arg_wit = rust_type_to_wit(arg_ty, ctx, &mut used_types)?;
+                arg_fragments.push(wit_type_name_fragment(&arg_wit));
+            }
+        }
+    }
+    Ok(format!("{}-{}", unescape(kebab_name), arg_fragments.join("-")))
+}
+
+// Turn a WIT type expression into a fragment usable inside a kebab-case name
+fn wit_type_name_fragment(wit_type: &str) -> String {
+    wit_type
+        .split(|c: char| c == '<' || c == '>' || c == ',' || c == '%' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// Resolve a use of a type alias, either inlining it or referencing its WIT definition
+fn alias_to_wit(
+    alias: &syn::ItemType,
+    arguments: &syn::PathArguments,
+    ctx: &mut TypeContext,
+    used_types: &mut HashSet<String>,
+) -> Result<String> {
+    let alias_name = alias.ident.to_string();
+    let is_generic = alias.generics.type_params().next().is_some();
+    let target = substitute_generics(&alias.ty, &alias.generics, arguments, &alias_name)?;
+
+    let mut alias_types = HashSet::new();
+    ctx.expanding.push(alias_name.clone());
+    let expanded = rust_type_to_wit(&target, ctx, &mut alias_types);
+    ctx.expanding.pop();
+    let expanded = expanded?;
+
+    if ctx.inline_aliases {
+        // The use site references whatever the alias expands to
+        used_types.extend(alias_types);
+        return Ok(expanded);
+    }
+
+    let kebab_alias = wit_identifier(&alias_name, "Type alias", &ctx.naming)?;
+    if !is_generic {
+        // Non-generic aliases are emitted once by collect_type_definitions
+        used_types.insert(kebab_alias.clone());
+        return Ok(kebab_alias);
+    }
+
+    // Each instantiation of a generic alias gets its own WIT name, e.g. `paged-order`
+    let instance_name = instance_name(&kebab_alias, arguments, ctx)?;
+    println!("    Instantiated alias {} as {}", alias_name, instance_name);
+    let location = ctx.location(alias.ident.span());
+    ctx.type_sources.insert(instance_name.clone(), location);
+    ctx.instantiations.insert(
+        instance_name.clone(),
+        TypeDef::new(format!("    type {} = {};", instance_name, expanded), alias_types),
+    );
+    used_types.insert(instance_name.clone());
+    Ok(instance_name)
+}
+
+// Resolve a use of a generic struct or enum to a monomorphized WIT definition
+fn generic_to_wit(
+    item: &Item,
+    arguments: &syn::PathArguments,
+    ctx: &mut TypeContext,
+    used_types: &mut HashSet<String>,
+) -> Result<String> {
+    let (ident, generics) = match item {
+        Item::Struct(item_struct) => (&item_struct.ident, &item_struct.generics),
+        Item::Enum(item_enum) => (&item_enum.ident, &item_enum.generics),
+        _ => anyhow::bail!("Error: only structs and enums can be instantiated"),
+    };
+    let orig_name = ident.to_string();
+    let kebab_name = wit_identifier(&orig_name, "Type", &ctx.naming)?;
+
+    let instance_name = instance_name(&kebab_name, arguments, ctx)?;
+    used_types.insert(instance_name.clone());
+
+    // Identical instantiations are only generated once
+    if ctx.instantiations.contains_key(&instance_name) {
+        return Ok(instance_name);
+    }
+    println!("    Instantiating {} as {}", orig_name, instance_name);
+    let location = ctx.location(ident.span());
+    ctx.type_sources.insert(instance_name.clone(), location);
+
+    // Reserve the name first so self-referential types don't instantiate forever
+    ctx.instantiations.insert(instance_name.clone(), TypeDef::default());
+
+    let params = generic_params(generics, arguments, &orig_name)?;
+    let mut instance = item.clone();
+    GenericSubstitution { params: &params }.visit_item_mut(&mut instance);
+
+    let mut instance_types = HashSet::new();
+    let def = match &instance {
+        Item::Struct(item_struct) => struct_to_wit(item_struct, &instance_name, ctx, &mut instance_types)?,
+        Item::Enum(item_enum) => enum_to_wit(item_enum, &instance_name, ctx, &mut instance_types)?,
+        _ => unreachable!(),
+    };
+    ctx.instantiations.insert(instance_name.clone(), TypeDef::new(def, instance_types));
+    Ok(instance_name)
+}
+
 // Convert Rust type to WIT type, including downstream types
-fn rust_type_to_wit(ty: &Type, used_types: &mut HashSet<String>) -> Result<String> {
+fn rust_type_to_wit(ty: &Type, ctx: &mut TypeContext, used_types: &mut HashSet<String>) -> Result<String> {
     match ty {
         Type::Path(type_path) => {
             if type_path.path.segments.is_empty() {
@@ -101,12 +474,42 @@ fn rust_type_to_wit(ty: &Type, used_types: &mut HashSet<String>) -> Result<Strin
             
             let ident = &type_path.path.segments.last().unwrap().ident;
             let type_name = ident.to_string();
+
+            if !ctx.expanding.contains(&type_name) {
+                if let Some(alias) = ctx.aliases.get(&type_name).cloned() {
+                    let arguments = &type_path.path.segments.last().unwrap().arguments;
+                    return alias_to_wit(&alias, arguments, ctx, used_types);
+                }
+            }
+
+            if let Some(standard_type) = standard_wit_type(type_path, ctx) {
+                used_types.insert(standard_type.to_string());
+                return Ok(standard_type.to_string());
+            }
+
+            if let Some(mapping) = mapped_type(type_path, ctx) {
+                let wit_name = mapping.wit_name.clone();
+                let def = mapping_to_wit(mapping);
+                ctx.mapped_types.insert(wit_name.clone(), def);
+                used_types.insert(wit_name.clone());
+                return Ok(wit_name);
+            }
+
+            if let Some(generic_item) = ctx.generic_items.get(&type_name).cloned() {
+                let arguments = &type_path.path.segments.last().unwrap().arguments;
+                return generic_to_wit(&generic_item, arguments, ctx, used_types);
+            }
             
             match type_name.as_str() {
+                "i8" => Ok("s8".to_string()),
+                "u8" => Ok("u8".to_string()),
+                "i16" => Ok("s16".to_string()),
+                "u16" => Ok("u16".to_string()),
                 "i32" => Ok("s32".to_string()),
                 "u32" => Ok("u32".to_string()),
-                "i64" => Ok("s64".to_string()),
-                "u64" => Ok("u64".to_string()),
+                "i64" | "isize" => Ok("s64".to_string()),
+                "u64" | "usize" => Ok("u64".to_string()),
+                "char" => Ok("char".to_string()),
                 "f32" => Ok("f32".to_string()),
                 "f64" => Ok("f64".to_string()),
                 "String" => Ok("string".to_string()),
@@ -116,7 +519,7 @@ fn rust_type_to_wit(ty: &Type, used_types: &mut HashSet<String>) -> Result<Strin
                         &type_path.path.segments.last().unwrap().arguments
                     {
                         if let Some(syn::GenericArgument::Type(inner_ty)) = args.args.first() {
-                            let inner_type = rust_type_to_wit(inner_ty, used_types)?;
+                            let inner_type = rust_type_to_wit(inner_ty, ctx, used_types)?;
                             Ok(format!("list<{}>", inner_type))
                         } else {
                             Ok("list<any>".to_string())
@@ -125,12 +528,23 @@ fn rust_type_to_wit(ty: &Type, used_types: &mut HashSet<String>) -> Result<Strin
                         Ok("list<any>".to_string())
                     }
                 }
+                // Smart pointers serialize as the value they point to
+                "Box" | "Rc" | "Arc" => {
+                    if let syn::PathArguments::AngleBracketed(args) =
+                        &type_path.path.segments.last().unwrap().arguments
+                    {
+                        if let Some(syn::GenericArgument::Type(inner_ty)) = args.args.first() {
+                            return rust_type_to_wit(inner_ty, ctx, used_types);
+                        }
+                    }
+                    Ok("unknown".to_string())
+                }
                 "Option" => {
                     if let syn::PathArguments::AngleBracketed(args) =
                         &type_path.path.segments.last().unwrap().arguments
                     {
                         if let Some(syn::GenericArgument::Type(inner_ty)) = args.args.first() {
-                            let inner_type = rust_type_to_wit(inner_ty, used_types)?;
+                            let inner_type = rust_type_to_wit(inner_ty, ctx, used_types)?;
                             Ok(format!("option<{}>", inner_type))
                         } else {
                             Ok("option<any>".to_string())
@@ -140,11 +554,8 @@ fn rust_type_to_wit(ty: &Type, used_types: &mut HashSet<String>) -> Result<Strin
                     }
                 }
                 custom => {
-                    // Validate custom type name
-                    validate_name(custom, "Type")?;
-                    
-                    // Convert custom type to kebab-case and add to used types
-                    let kebab_custom = to_kebab_case(custom);
+                    // Convert custom type to a WIT identifier and add to used types
+                    let kebab_custom = wit_identifier(custom, "Type", &ctx.naming)?;
                     used_types.insert(kebab_custom.clone());
                     Ok(kebab_custom)
                 }
@@ -152,17 +563,17 @@ fn rust_type_to_wit(ty: &Type, used_types: &mut HashSet<String>) -> Result<Strin
         }
         Type::Reference(type_ref) => {
             // Handle references by using the underlying type
-            rust_type_to_wit(&type_ref.elem, used_types)
+            rust_type_to_wit(&type_ref.elem, ctx, used_types)
         }
         Type::Tuple(type_tuple) => {
             if type_tuple.elems.is_empty() {
-                // Empty tuple is unit in WIT
-                Ok("unit".to_string())
+                // WIT has no unit type; the empty tuple serializes the same way
+                Ok("tuple<>".to_string())
             } else {
                 // Create a tuple representation in WIT
                 let mut elem_types = Vec::new();
                 for elem in &type_tuple.elems {
-                    elem_types.push(rust_type_to_wit(elem, used_types)?);
+                    elem_types.push(rust_type_to_wit(elem, ctx, used_types)?);
                 }
                 Ok(format!("tuple<{}>", elem_types.join(", ")))
             }
@@ -171,119 +582,858 @@ fn rust_type_to_wit(ty: &Type, used_types: &mut HashSet<String>) -> Result<Strin
     }
 }
 
+// A single flags type declared inside a `bitflags!` invocation
+struct BitflagsDef {
+    attrs: Vec<Attribute>,
+    ident: syn::Ident,
+    // Each named flag with its attributes
+    flags: Vec<(syn::Ident, Vec<Attribute>)>,
+}
+
+// The contents of a `bitflags!` invocation, which may declare several types
+struct BitflagsMacro {
+    defs: Vec<BitflagsDef>,
+}
+
+impl Parse for BitflagsMacro {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut defs = Vec::new();
+        while !input.is_empty() {
+            // #[attrs] pub struct Name: Bits { ... }
+            let attrs = input.call(Attribute::parse_outer)?;
+            input.parse::<syn::Visibility>()?;
+            input.parse::<Token![struct]>()?;
+            let ident: syn::Ident = input.parse()?;
+            input.parse::<Token![:]>()?;
+            input.parse::<Type>()?;
+
+            let body;
+            braced!(body in input);
+            let mut flags = Vec::new();
+            while !body.is_empty() {
+                // #[attrs] const NAME = value;
+                let flag_attrs = body.call(Attribute::parse_outer)?;
+                body.parse::<Token![const]>()?;
+                // `const _ = !0;` only declares external bits and has no name
+                let flag = if body.peek(Token![_]) {
+                    body.parse::<Token![_]>()?;
+                    None
+                } else {
+                    Some(body.parse::<syn::Ident>()?)
+                };
+                body.parse::<Token![=]>()?;
+                body.parse::<syn::Expr>()?;
+                body.parse::<Token![;]>()?;
+                if let Some(flag) = flag {
+                    flags.push((flag, flag_attrs));
+                }
+            }
+
+            defs.push(BitflagsDef { attrs, ident, flags });
+        }
+        Ok(BitflagsMacro { defs })
+    }
+}
+
+// Check whether a macro invocation is `bitflags!` (or `bitflags::bitflags!`)
+fn is_bitflags_macro(item_macro: &syn::ItemMacro) -> bool {
+    item_macro
+        .mac
+        .path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "bitflags")
+}
+
+// Comment prefix recording serde attributes the caller-utils type of a field or variant needs
+// to keep the wire format, e.g. `// Serde: rename = "userName"`
+pub const SERDE_COMMENT: &str = "Serde: ";
+
+// The `// Serde:` comment line at `indent` for the given attribute arguments, if any
+fn serde_comment(args: &[String], indent: &str) -> String {
+    if args.is_empty() {
+        String::new()
+    } else {
+        format!("{}// {}{}\n", indent, SERDE_COMMENT, args.join(", "))
+    }
+}
+
+// Work out the WIT name of a field or variant from the JSON key serde uses for it, and the
+// key to rename it to in caller-utils if bindings generated from the WIT name would use a
+// different one
+fn wire_and_wit_name(
+    ident: &syn::Ident,
+    serde: &SerdeAttrs,
+    rename_all: Option<&str>,
+    kind: &str,
+    ctx: &TypeContext,
+) -> Result<(String, Option<String>)> {
+    let is_variant = kind == "Enum variant";
+    let rust_name = ident.unraw().to_string();
+    let wire_name = match (&serde.rename, rename_all) {
+        (Some(rename), _) => rename.clone(),
+        (None, Some(rule)) if is_variant => serde_attrs::rename_variant(rule, &rust_name)?,
+        (None, Some(rule)) => serde_attrs::rename_field(rule, &rust_name)?,
+        (None, None) => rust_name.clone(),
+    };
+    let wit_name = wit_identifier(&wire_name, kind, &ctx.naming)?;
+
+    // Bindings generated from WIT use snake_case fields and PascalCase variants, with Rust
+    // keywords suffixed by `_`, so a key only survives the round trip if it follows the same
+    // convention. Renames, mangled digits (`enable_2fa` -> `enable2fa`) and escaped keywords
+    // can all break it.
+    let binding_name = if is_variant {
+        to_pascal_case(unescape(&wit_name))
+    } else {
+        to_rust_identifier(to_snake_case(unescape(&wit_name)))
+    };
+
+    if binding_name == wire_name {
+        return Ok((wit_name, None));
+    }
+    println!(
+        "    {} '{}' is \"{}\" in the JSON, which bindings generated from its WIT name '{}' would \
+         call \"{}\"; caller-utils keeps the JSON name",
+        kind.to_lowercase(),
+        rust_name,
+        wire_name,
+        wit_name,
+        binding_name
+    );
+    Ok((wit_name, Some(wire_name)))
+}
+
+// Warn when custom (de)serialization makes the wire format differ from the Rust type
+fn warn_custom_serde(serde: &SerdeAttrs, what: &str) {
+    if let Some(custom) = &serde.custom {
+        println!(
+            "    Warning: {} uses #[serde({})]; the WIT type reflects the Rust type, not its custom serialization",
+            what, custom
+        );
+    }
+}
+
+// WIT `///` doc lines, at `indent`, for the doc comments of a Rust item
+fn doc_comment(attrs: &[Attribute], indent: &str) -> String {
+    let mut docs = String::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("doc")) {
+        // `#[doc = include_str!(...)]` and the like can't be followed
+        let syn::Meta::NameValue(meta) = &attr.meta else {
+            continue;
+        };
+        let syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(text), .. }) = &meta.value else {
+            continue;
+        };
+        for line in text.value().split('\n') {
+            // `/// text` is `#[doc = " text"]`
+            let line = line.strip_prefix(' ').unwrap_or(line).trim_end();
+            if line.is_empty() {
+                docs.push_str(&format!("{}///\n", indent));
+            } else {
+                docs.push_str(&format!("{}/// {}\n", indent, line));
+            }
+        }
+    }
+    docs
+}
+
+// First line of the doc note added to deprecated items, followed by the deprecation note
+pub const DEPRECATED_DOC: &str = "Deprecated";
+
+// The `since` and `note` of a `#[deprecated]` attribute, if the item has one
+fn deprecation(attrs: &[Attribute]) -> Result<Option<(Option<String>, Option<String>)>> {
+    let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("deprecated")) else {
+        return Ok(None);
+    };
+    let (mut since, mut note) = (None, None);
+    match &attr.meta {
+        syn::Meta::Path(_) => {}
+        // #[deprecated = "note"]
+        syn::Meta::NameValue(meta) => {
+            if let syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(text), .. }) = &meta.value {
+                note = Some(text.value());
+            }
+        }
+        // #[deprecated(since = "1.2.0", note = "...")]
+        syn::Meta::List(_) => attr.parse_nested_meta(|meta| {
+            let value = meta.value()?.parse::<syn::LitStr>()?.value();
+            if meta.path.is_ident("since") {
+                since = Some(value);
+            } else if meta.path.is_ident("note") {
+                note = Some(value);
+            }
+            Ok(())
+        })?,
+    }
+    Ok(Some((since, note)))
+}
+
+// Whether a version is semver, as WIT feature gates require
+fn is_semver(version: &str) -> bool {
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3 && parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+// Major, minor and patch of a semver version, for ordering versions
+fn version_core(version: &str) -> Vec<u64> {
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+    core.split('.').map(|part| part.parse().unwrap_or_default()).collect()
+}
+
+// WIT for the stability of a Rust item at `indent`: a `///` note with its deprecation note and,
+// for types and functions (`gated`), the `@since`/`@unstable` and `@deprecated` gates. Handlers
+// kept behind a Cargo feature (see `[cfg] feature-gates`) are `@unstable` behind it. WIT has no
+// gates for fields and variant cases.
+fn stability_wit(
+    attrs: &[Attribute],
+    indent: &str,
+    gated: bool,
+    item: &str,
+    span: proc_macro2::Span,
+    ctx: &TypeContext,
+) -> Result<String> {
+    let deprecated = deprecation(attrs)?;
+    let feature = if gated { feature_gate(attrs) } else { None };
+    if deprecated.is_none() && feature.is_none() {
+        return Ok(String::new());
+    }
+    let mut wit = String::new();
+    if let Some((_, note)) = &deprecated {
+        println!("    Deprecated: {}", item);
+        wit = match note {
+            Some(note) => format!("{}/// {}: {}\n", indent, DEPRECATED_DOC, note.replace('\n', " ")),
+            None => format!("{}/// {}\n", indent, DEPRECATED_DOC),
+        };
+    }
+    if !gated {
+        return Ok(wit);
+    }
+
+    let version = || match ctx.deprecation.since.clone() {
+        Some(version) => Ok(version),
+        None => Err(anyhow::anyhow!(
+            "Error: {} at {} is #[deprecated], which needs a version for its WIT feature gates; \
+             set `version` under [package] or `since` under [deprecation] in {}",
+            item,
+            ctx.location(span),
+            CONFIG_FILE_NAME
+        )),
+    };
+    match (&feature, &ctx.deprecation.feature) {
+        (Some(feature), _) => {
+            let feature = wit_identifier(feature, "Feature", &ctx.naming)?;
+            wit.push_str(&format!("{}@unstable(feature = {})\n", indent, feature));
+        }
+        (None, Some(feature)) => wit.push_str(&format!("{}@unstable(feature = {})\n", indent, feature)),
+        (None, None) => wit.push_str(&format!("{}@since(version = {})\n", indent, version()?)),
+    }
+    if let Some((since, _)) = deprecated {
+        // The Rust `since` is kept if it's a version WIT accepts and isn't older than `@since`
+        let deprecated = match since.filter(|since| is_semver(since)) {
+            Some(since) if feature.is_none() && ctx.deprecation.feature.is_none() => {
+                let available = version()?;
+                if version_core(&since) < version_core(&available) {
+                    println!(
+                        "    {} is deprecated since {}, before the {} it's available since; using {}",
+                        item, since, available, available
+                    );
+                    available
+                } else {
+                    since
+                }
+            }
+            Some(since) => since,
+            None => version()?,
+        };
+        wit.push_str(&format!("{}@deprecated(version = {})\n", indent, deprecated));
+    }
+    Ok(wit)
+}
+
+// Convert named struct fields into WIT record field lines, honouring serde attributes
+fn named_fields_to_wit(
+    fields: &syn::FieldsNamed,
+    rename_all: Option<&str>,
+    container_default: bool,
+    container: &str,
+    ctx: &mut TypeContext,
+    used_types: &mut HashSet<String>,
+) -> Result<Vec<String>> {
+    let mut field_strings = Vec::new();
+
+    for f in &fields.named {
+        if let Some(field_ident) = &f.ident {
+            let serde = SerdeAttrs::parse(&f.attrs)?;
+
+            // Skipped fields never appear in the JSON
+            if serde.skip() {
+                println!("    Skipping field {} (serde skip)", field_ident);
+                continue;
+            }
+
+            // Flattened fields contribute their own fields to this record
+            if serde.flatten {
+                println!("    Flattening field {}", field_ident);
+                field_strings.extend(flattened_fields_to_wit(&f.ty, container, ctx, used_types)?);
+                continue;
+            }
+
+            warn_custom_serde(&serde, &format!("field '{}'", field_ident));
+
+            // Field names follow their serialized name, converted to kebab-case
+            let (field_name, rename) = wire_and_wit_name(field_ident, &serde, rename_all, "Field", ctx)?;
+            let mut serde_args: Vec<String> = rename.iter().map(|wire| format!("rename = {:?}", wire)).collect();
+            let mut field_type = field_type_to_wit(&f.ty, container, ctx, used_types, Some(&mut serde_args))?;
+            let json_string = serde_args.iter().any(|arg| arg == JSON_STRING_SERDE);
+
+            // A field that is only sent one way, or filled in when missing, is optional on the
+            // wire; caller-utils leaves it out when it is `None`
+            if (serde.optional() || container_default) && json_string {
+                anyhow::bail!(
+                    "Error: field '{}' at {} closes a recursive type cycle and is optional on the wire, \
+                     which the JSON string encoding can't express",
+                    field_ident,
+                    ctx.location(field_ident.span())
+                );
+            }
+            if (serde.optional() || container_default) && !field_type.starts_with("option<") {
+                println!("    Field {} is optional on the wire", field_name);
+                field_type = format!("option<{}>", field_type);
+                serde_args.push("default".to_string());
+                serde_args.push("skip_serializing_if = \"Option::is_none\"".to_string());
+            }
+            println!("    Field: {} -> {}", field_name, field_type);
+            let deprecated = stability_wit(
+                &f.attrs, "        ", false, &format!("field '{}'", field_ident), field_ident.span(), ctx
+            )?;
+            field_strings.push(format!(
+                "{}{}{}        {}: {}",
+                doc_comment(&f.attrs, "        "),
+                deprecated,
+                serde_comment(&serde_args, "        "),
+                field_name,
+                field_type
+            ));
+        }
+    }
+
+    Ok(field_strings)
+}
+
+// Serde arguments of a field or payload encoded as a JSON string because it closes a recursive
+// type cycle. caller-utils converts it with its `json_string` module, so the value is still
+// sent as plain JSON.
+pub const JSON_STRING_SERDE: &str = "with = \"crate::json_string\"";
+
+// Convert the type of a field or payload of `container`, using the recursive fallback
+// instead if it is a reference that closes a cycle. The fallback needs the serde arguments of a
+// named field or single payload (`serde_args`) to record the JSON string conversion.
+fn field_type_to_wit(
+    ty: &Type,
+    container: &str,
+    ctx: &mut TypeContext,
+    used_types: &mut HashSet<String>,
+    serde_args: Option<&mut Vec<String>>,
+) -> Result<String> {
+    let mut field_types = HashSet::new();
+    let field_type = rust_type_to_wit(ty, ctx, &mut field_types)?;
+
+    if let Some(fallback) = &ctx.recursive_fallback {
+        let closes_cycle = field_types
+            .iter()
+            .any(|t| ctx.recursive_fields.contains(&(container.to_string(), t.clone())));
+        if closes_cycle {
+            let Some(serde_args) = serde_args else {
+                anyhow::bail!(
+                    "Error: recursive {} in {} at {} closes a type cycle inside a tuple or alias, which \
+                     callers can't convert from a JSON {}; put it in a named field or enum variant",
+                    field_type,
+                    container,
+                    ctx.location(ty.span()),
+                    fallback
+                );
+            };
+            println!("    Warning: encoding recursive {} in {} as a JSON {}", field_type, container, fallback);
+            serde_args.push(JSON_STRING_SERDE.to_string());
+            return Ok(fallback.clone());
+        }
+    }
+
+    used_types.extend(field_types);
+    Ok(field_type)
+}
+
+// Inline the fields of a #[serde(flatten)] struct into the record that contains it
+fn flattened_fields_to_wit(
+    ty: &Type,
+    container: &str,
+    ctx: &mut TypeContext,
+    used_types: &mut HashSet<String>,
+) -> Result<Vec<String>> {
+    let segment = match ty {
+        Type::Path(type_path) if !type_path.path.segments.is_empty() => {
+            type_path.path.segments.last().unwrap()
+        }
+        _ => anyhow::bail!("Error: only structs can be flattened into a WIT record"),
+    };
+    let type_name = segment.ident.to_string();
+
+    let mut item_struct = match ctx.structs.get(&type_name) {
+        Some(item_struct) => item_struct.clone(),
+        None => anyhow::bail!(
+            "Error: cannot flatten '{}' into a WIT record; only structs with named fields declared in the same file can be flattened",
+            type_name
+        ),
+    };
+
+    if item_struct.generics.type_params().next().is_some() {
+        let params = generic_params(&item_struct.generics, &segment.arguments, &type_name)?;
+        GenericSubstitution { params: &params }.visit_item_struct_mut(&mut item_struct);
+    }
+
+    let serde = SerdeAttrs::parse(&item_struct.attrs)?;
+    match &item_struct.fields {
+        syn::Fields::Named(fields) => {
+            named_fields_to_wit(fields, serde.rename_all.as_deref(), serde.default, container, ctx, used_types)
+        }
+        _ => anyhow::bail!(
+            "Error: cannot flatten '{}' into a WIT record; it has no named fields",
+            type_name
+        ),
+    }
+}
+
+// Render a WIT record definition from its field lines
+fn record_to_wit(name: &str, field_strings: &[String]) -> String {
+    if field_strings.is_empty() {
+        // Records without fields serialize as an empty JSON object
+        format!("    record {} {{}}", name)
+    } else {
+        format!("    record {} {{\n{}\n    }}", name, field_strings.join(",\n")) // Add comma separator
+    }
+}
+
+// Render a struct as a WIT record or type alias, following its serde representation
+fn struct_to_wit(
+    item_struct: &syn::ItemStruct,
+    name: &str,
+    ctx: &mut TypeContext,
+    used_types: &mut HashSet<String>,
+) -> Result<String> {
+    let container = SerdeAttrs::parse(&item_struct.attrs)?;
+    warn_custom_serde(&container, &format!("struct '{}'", item_struct.ident));
+
+    if container.tag.is_some() {
+        anyhow::bail!(
+            "Error: struct '{}' uses #[serde(tag = ...)], which adds a field WIT records cannot represent",
+            item_struct.ident
+        );
+    }
+
+    // A transparent struct serializes exactly like its single non-skipped field
+    if container.transparent {
+        let mut inner = Vec::new();
+        for f in &item_struct.fields {
+            if !SerdeAttrs::parse(&f.attrs)?.skip() {
+                inner.push(&f.ty);
+            }
+        }
+        if inner.len() != 1 {
+            anyhow::bail!(
+                "Error: #[serde(transparent)] struct '{}' must have exactly one non-skipped field",
+                item_struct.ident
+            );
+        }
+        let aliased = field_type_to_wit(inner[0], name, ctx, used_types, None)?;
+        println!("    Transparent struct: {} = {}", name, aliased);
+        return Ok(format!("    type {} = {};", name, aliased));
+    }
+
+    match &item_struct.fields {
+        syn::Fields::Named(fields) => {
+            let field_strings = named_fields_to_wit(
+                fields, container.rename_all.as_deref(), container.default, name, ctx, used_types
+            )?;
+            Ok(record_to_wit(name, &field_strings))
+        }
+        syn::Fields::Unnamed(fields) => {
+            let mut elem_types = Vec::new();
+            for f in &fields.unnamed {
+                if SerdeAttrs::parse(&f.attrs)?.skip() {
+                    continue;
+                }
+                elem_types.push(field_type_to_wit(&f.ty, name, ctx, used_types, None)?);
+            }
+
+            // Serde serializes a newtype struct as its inner value and any
+            // other tuple struct as a JSON array, so alias accordingly
+            let aliased = match elem_types.len() {
+                0 => return Ok(record_to_wit(name, &[])),
+                1 => elem_types.remove(0),
+                _ => format!("tuple<{}>", elem_types.join(", ")),
+            };
+            println!("    Tuple struct: {} = {}", name, aliased);
+            Ok(format!("    type {} = {};", name, aliased))
+        }
+        syn::Fields::Unit => {
+            println!("    Unit struct: {}", name);
+            Ok(record_to_wit(name, &[]))
+        }
+    }
+}
+
+// Render an enum as a WIT enum (all unit variants) or variant. Records generated for
+// struct-like variants are added to the context to be emitted alongside it.
+fn enum_to_wit(
+    item_enum: &syn::ItemEnum,
+    name: &str,
+    ctx: &mut TypeContext,
+    used_types: &mut HashSet<String>,
+) -> Result<String> {
+    let orig_name = item_enum.ident.to_string();
+    let container = SerdeAttrs::parse(&item_enum.attrs)?;
+    warn_custom_serde(&container, &format!("enum '{}'", orig_name));
+
+    // WIT variants are always externally tagged, like serde's default representation
+    if container.untagged {
+        anyhow::bail!(
+            "Error: enum '{}' uses #[serde(untagged)], which WIT variants cannot represent \
+             (they serialize externally tagged as {{\"Variant\": payload}})",
+            orig_name
+        );
+    }
+    if let Some(tag) = &container.tag {
+        let representation = match &container.content {
+            Some(content) => format!("adjacently tagged (tag = \"{}\", content = \"{}\")", tag, content),
+            None => format!("internally tagged (tag = \"{}\")", tag),
+        };
+        anyhow::bail!(
+            "Error: enum '{}' is {}, which WIT variants cannot represent \
+             (they serialize externally tagged as {{\"Variant\": payload}})",
+            orig_name,
+            representation
+        );
+    }
+
+    // Skipped variants can't be sent or received, so leave them out. Variants skipped in one
+    // direction are kept for the other.
+    let mut included = Vec::new();
+    for v in &item_enum.variants {
+        let serde = SerdeAttrs::parse(&v.attrs)?;
+        if serde.skip() {
+            println!("    Skipping variant {} (serde skip)", v.ident);
+            continue;
+        }
+        warn_custom_serde(&serde, &format!("variant '{}::{}'", orig_name, v.ident));
+        included.push((v, serde));
+    }
+
+    // Fieldless enums map to the cheaper WIT `enum`
+    let all_unit = !included.is_empty()
+        && included.iter().all(|(v, _)| matches!(v.fields, syn::Fields::Unit));
+    if all_unit {
+        let mut cases = Vec::new();
+        for (v, serde) in &included {
+            let (case_name, rename) = wire_and_wit_name(
+                &v.ident, serde, container.rename_all.as_deref(), "Enum variant", ctx
+            )?;
+            println!("    Case: {}", case_name);
+            let deprecated = stability_wit(
+                &v.attrs, "        ", false, &format!("variant '{}::{}'", orig_name, v.ident), v.ident.span(), ctx
+            )?;
+            let serde_args: Vec<String> = rename.iter().map(|wire| format!("rename = {:?}", wire)).collect();
+            cases.push(format!(
+                "{}{}{}        {}",
+                doc_comment(&v.attrs, "        "),
+                deprecated,
+                serde_comment(&serde_args, "        "),
+                case_name
+            ));
+        }
+        return Ok(format!("    enum {} {{\n{}\n    }}", name, cases.join(",\n")));
+    }
+
+    let mut variants = Vec::new();
+    for (v, serde) in &included {
+        let variant_orig_name = v.ident.to_string();
+
+        // Variant names follow their serialized name, converted to kebab-case
+        let (variant_name, rename) = wire_and_wit_name(
+            &v.ident, serde, container.rename_all.as_deref(), "Enum variant", ctx
+        )?;
+        let mut serde_args: Vec<String> = rename.iter().map(|wire| format!("rename = {:?}", wire)).collect();
+        // A single payload may add the serde arguments of its JSON string encoding
+        let payload = match &v.fields {
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Some(field_type_to_wit(
+                &fields.unnamed.first().unwrap().ty,
+                name,
+                ctx,
+                used_types,
+                Some(&mut serde_args),
+            )?),
+            _ => None,
+        };
+        let docs = doc_comment(&v.attrs, "        ") + &stability_wit(
+            &v.attrs, "        ", false, &format!("variant '{}::{}'", orig_name, v.ident), v.ident.span(), ctx
+        )? + &serde_comment(&serde_args, "        ");
+
+        match (&v.fields, payload) {
+            (_, Some(ty)) => {
+                // Use parentheses for the payload type
+                println!("    Variant: {} -> {}", variant_name, ty);
+                variants.push(format!("{}        {}({})", docs, variant_name, ty));
+            }
+            (syn::Fields::Unnamed(fields), None) if fields.unnamed.len() > 1 => {
+                // Multi-field tuple variants carry a tuple payload
+                let mut elem_types = Vec::new();
+                for f in &fields.unnamed {
+                    elem_types.push(field_type_to_wit(&f.ty, name, ctx, used_types, None)?);
+                }
+                let ty = format!("tuple<{}>", elem_types.join(", "));
+                println!("    Variant: {} -> {}", variant_name, ty);
+                variants.push(format!("{}        {}({})", docs, variant_name, ty));
+            }
+            (syn::Fields::Named(fields), None) => {
+                // Struct-like variants carry an auxiliary record as payload
+                let record_name = ctx.variant_record_name(name, &variant_name);
+                let fields_rename_all = serde.rename_all.as_deref()
+                    .or(container.rename_all_fields.as_deref());
+                let mut record_types = HashSet::new();
+                let field_strings = named_fields_to_wit(
+                    fields, fields_rename_all, false, &record_name, ctx, &mut record_types
+                )?;
+                println!("    Variant: {} -> {} (struct-like)", variant_name, record_name);
+                let location = ctx.location(v.ident.span());
+                ctx.type_sources.insert(record_name.clone(), location.clone());
+                ctx.variant_records.push((
+                    record_name.clone(),
+                    TypeDef::new(record_to_wit(&record_name, &field_strings), record_types),
+                    format!("{}::{} at {}", orig_name, variant_orig_name, location),
+                ));
+                used_types.insert(record_name.clone());
+                variants.push(format!("{}        {}({})", docs, variant_name, record_name));
+            }
+            _ => {
+                println!("    Variant: {}", variant_name);
+                variants.push(format!("{}        {}", docs, variant_name));
+            }
+        }
+    }
+
+    Ok(format!("    variant {} {{\n{}\n    }}", name, variants.join(",\n"))) // Add comma separator
+}
+
 // Collect type definitions (structs and enums) from the file
-fn collect_type_definitions(ast: &syn::File) -> Result<HashMap<String, String>> {
+fn collect_type_definitions(ast: &syn::File, ctx: &mut TypeContext) -> Result<HashMap<String, TypeDef>> {
     let mut type_defs = HashMap::new();
+    // Rust item and location each declared WIT type name comes from
+    let mut declared_at = HashMap::new();
     
     println!("Collecting type definitions from file");
     for item in &ast.items {
         match item {
             Item::Struct(item_struct) => {
-                // Validate struct name doesn't contain numbers or "stream"
+                // Generic structs are emitted per instantiation
+                if item_struct.generics.type_params().next().is_some() {
+                    continue;
+                }
+
+                // Use a kebab-case WIT identifier for the struct name
                 let orig_name = item_struct.ident.to_string();
-                validate_name(&orig_name, "Struct")?;
-                
-                // Use kebab-case for struct name
-                let name = to_kebab_case(&orig_name);
+                let name = wit_identifier(&orig_name, "Struct", &ctx.naming)?;
                 println!("  Found struct: {}", name);
+                let location = ctx.location(item_struct.ident.span());
+                claim_wit_name(&mut declared_at, &name, "types", &orig_name, &location)?;
+                ctx.type_sources.entry(name.clone()).or_insert(location);
                 
-                let fields: Vec<String> = match &item_struct.fields {
-                    syn::Fields::Named(fields) => {
-                        let mut used_types = HashSet::new();
-                        let mut field_strings = Vec::new();
-                        
-                        for f in &fields.named {
-                            if let Some(field_ident) = &f.ident {
-                                // Validate field name doesn't contain digits
-                                let field_orig_name = field_ident.to_string();
-                                validate_name(&field_orig_name, "Field")?;
-                                
-                                // Convert field names to kebab-case
-                                let field_name = to_kebab_case(&field_orig_name);
-                                let field_type = rust_type_to_wit(&f.ty, &mut used_types)?;
-                                println!("    Field: {} -> {}", field_name, field_type);
-                                field_strings.push(format!("        {}: {}", field_name, field_type));
-                            }
-                        }
-                        
-                        field_strings
+                let mut used_types = HashSet::new();
+                match struct_to_wit(item_struct, &name, ctx, &mut used_types) {
+                    Ok(def) => {
+                        let deprecated = stability_wit(
+                            &item_struct.attrs, "    ", true, &format!("struct '{}'", orig_name), item_struct.ident.span(), ctx
+                        )?;
+                        let def = format!("{}{}{}", doc_comment(&item_struct.attrs, "    "), deprecated, def);
+                        type_defs.insert(name, TypeDef::new(def, used_types));
+                    }
+                    Err(e) => {
+                        println!("    Deferring error for {}: {}", name, e);
+                        ctx.type_errors.insert(name, e);
                     }
-                    _ => Vec::new(),
-                };
-                
-                if !fields.is_empty() {
-                    type_defs.insert(
-                        name.clone(),
-                        format!("    record {} {{\n{}\n    }}", name, fields.join(",\n")), // Add comma separator
-                    );
                 }
             }
             Item::Enum(item_enum) => {
-                // Validate enum name doesn't contain numbers or "stream"
+                // Generic enums are emitted per instantiation
+                if item_enum.generics.type_params().next().is_some() {
+                    continue;
+                }
+
+                // Use a kebab-case WIT identifier for the enum name
                 let orig_name = item_enum.ident.to_string();
-                validate_name(&orig_name, "Enum")?;
-                
-                // Use kebab-case for enum name
-                let name = to_kebab_case(&orig_name);
+                let name = wit_identifier(&orig_name, "Enum", &ctx.naming)?;
                 println!("  Found enum: {}", name);
+                let location = ctx.location(item_enum.ident.span());
+                claim_wit_name(&mut declared_at, &name, "types", &orig_name, &location)?;
+                ctx.type_sources.entry(name.clone()).or_insert(location);
                 
-                let variants: Vec<String> = item_enum
-                    .variants
-                    .iter()
-                    .map(|v| {
-                        let variant_orig_name = v.ident.to_string();
-                        // Validate variant name
-                        validate_name(&variant_orig_name, "Enum variant")?;
-                        
-                        match &v.fields {
-                            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
-                                let mut used_types = HashSet::new();
-                                let ty = rust_type_to_wit(
-                                    &fields.unnamed.first().unwrap().ty,
-                                    &mut used_types
-                                )?;
-                                
-                                // Use kebab-case for variant names and use parentheses for type
-                                let variant_name = to_kebab_case(&variant_orig_name);
-                                println!("    Variant: {} -> {}", variant_name, ty);
-                                Ok(format!("        {}({})", variant_name, ty))
-                            }
-                            syn::Fields::Unit => {
-                                // Use kebab-case for variant names
-                                let variant_name = to_kebab_case(&variant_orig_name);
-                                println!("    Variant: {}", variant_name);
-                                Ok(format!("        {}", variant_name))
-                            },
-                            _ => {
-                                // Use kebab-case for variant names
-                                let variant_name = to_kebab_case(&variant_orig_name);
-                                println!("    Variant: {} (complex)", variant_name);
-                                Ok(format!("        {}", variant_name))
-                            },
-                        }
-                    })
-                    .collect::<Result<Vec<String>>>()?;
-                
-                type_defs.insert(
-                    name.clone(),
-                    format!("    variant {} {{\n{}\n    }}", name, variants.join(",\n")), // Add comma separator
-                );
+                let mut used_types = HashSet::new();
+                match enum_to_wit(item_enum, &name, ctx, &mut used_types) {
+                    Ok(def) => {
+                        let deprecated = stability_wit(
+                            &item_enum.attrs, "    ", true, &format!("enum '{}'", orig_name), item_enum.ident.span(), ctx
+                        )?;
+                        let def = format!("{}{}{}", doc_comment(&item_enum.attrs, "    "), deprecated, def);
+                        type_defs.insert(name, TypeDef::new(def, used_types));
+                    }
+                    Err(e) => {
+                        println!("    Deferring error for {}: {}", name, e);
+                        ctx.type_errors.insert(name, e);
+                    }
+                }
+            }
+            Item::Macro(item_macro) if is_bitflags_macro(item_macro) => {
+                let bitflags: BitflagsMacro = item_macro.mac.parse_body()
+                    .with_context(|| "Failed to parse bitflags! invocation")?;
+
+                for def in bitflags.defs {
+                    let orig_name = def.ident.to_string();
+                    let name = wit_identifier(&orig_name, "Flags", &ctx.naming)?;
+                    println!("  Found bitflags: {}", name);
+                    let location = ctx.location(def.ident.span());
+                    claim_wit_name(&mut declared_at, &name, "types", &orig_name, &location)?;
+                    ctx.type_sources.entry(name.clone()).or_insert(location);
+
+                    let mut flags = Vec::new();
+                    for (flag, flag_attrs) in &def.flags {
+                        // Flag constants are SCREAMING_CASE, WIT flags are kebab-case
+                        let flag_orig_name = flag.to_string();
+                        let flag_name = wit_identifier(&flag_orig_name.to_lowercase(), "Flag", &ctx.naming)?;
+                        println!("    Flag: {}", flag_name);
+                        let deprecated = stability_wit(
+                            flag_attrs, "        ", false, &format!("flag '{}::{}'", orig_name, flag), flag.span(), ctx
+                        )?;
+                        flags.push(format!("{}{}        {}", doc_comment(flag_attrs, "        "), deprecated, flag_name));
+                    }
+
+                    if flags.is_empty() {
+                        println!("    Skipping bitflags type without any named flags");
+                        continue;
+                    }
+
+                    let deprecated = stability_wit(
+                        &def.attrs, "    ", true, &format!("bitflags '{}'", orig_name), def.ident.span(), ctx
+                    )?;
+                    type_defs.insert(
+                        name.clone(),
+                        TypeDef::new(
+                            format!(
+                                "{}{}    flags {} {{\n{}\n    }}",
+                                doc_comment(&def.attrs, "    "),
+                                deprecated,
+                                name,
+                                flags.join(",\n")
+                            ),
+                            HashSet::new(),
+                        ),
+                    );
+                }
+            }
+            Item::Type(item_type) => {
+                // Generic aliases are emitted per instantiation, and inlined aliases not at all
+                if ctx.inline_aliases || item_type.generics.type_params().next().is_some() {
+                    continue;
+                }
+
+                let orig_name = item_type.ident.to_string();
+                let name = wit_identifier(&orig_name, "Type alias", &ctx.naming)?;
+                let location = ctx.location(item_type.ident.span());
+                claim_wit_name(&mut declared_at, &name, "types", &orig_name, &location)?;
+                ctx.type_sources.entry(name.clone()).or_insert(location);
+                let mut used_types = HashSet::new();
+                ctx.expanding.push(orig_name.clone());
+                let aliased = field_type_to_wit(&item_type.ty, &name, ctx, &mut used_types, None);
+                ctx.expanding.pop();
+                let aliased = aliased?;
+
+                println!("  Found type alias: {} = {}", name, aliased);
+                let deprecated = stability_wit(
+                    &item_type.attrs, "    ", true, &format!("type alias '{}'", orig_name), item_type.ident.span(), ctx
+                )?;
+                let def = format!("{}{}    type {} = {};", doc_comment(&item_type.attrs, "    "), deprecated, name, aliased);
+                type_defs.insert(name.clone(), TypeDef::new(def, used_types));
             }
             _ => {}
         }
     }
+
+    // Add auxiliary variant records, making sure they don't shadow other types
+    for (record_name, record_def, variant) in std::mem::take(&mut ctx.variant_records) {
+        let other = match declared_at.get(&record_name) {
+            Some((other_name, other_location)) => Some(format!("type '{}' at {}", other_name, other_location)),
+            None if type_defs.contains_key(&record_name) => Some("another type of the same name".to_string()),
+            None => None,
+        };
+        if let Some(other) = other {
+            anyhow::bail!(
+                "Error: record '{}' generated for enum variant {} collides with {}; \
+                 change `variant-record-name` in {}",
+                record_name,
+                variant,
+                other,
+                CONFIG_FILE_NAME
+            );
+        }
+        type_defs.insert(record_name, record_def);
+    }
+
+    // Add mapped types used so far
+    for (name, def) in &ctx.mapped_types {
+        if let Some((other_name, other_location)) = declared_at.get(name) {
+            anyhow::bail!(
+                "Error: WIT type '{}' for a mapped type collides with type '{}' at {}; \
+                 set a different `name` for it under [types.mappings] in {}",
+                name,
+                other_name,
+                other_location,
+                CONFIG_FILE_NAME
+            );
+        }
+        type_defs.insert(name.clone(), TypeDef::new(def.clone(), HashSet::new()));
+    }
+
+    // Add instantiations of generic aliases and types used so far
+    for (name, def) in &ctx.instantiations {
+        let other = match declared_at.get(name) {
+            Some((other_name, other_location)) => Some(format!("type '{}' at {}", other_name, other_location)),
+            None if type_defs.contains_key(name) => Some("another type of the same name".to_string()),
+            None => None,
+        };
+        if let Some(other) = other {
+            let location = ctx.type_sources.get(name).map(String::as_str).unwrap_or("unknown location");
+            anyhow::bail!(
+                "Error: generic instantiation '{}' of the type at {} collides with {}; \
+                 give one of them a different WIT name under [naming.renames] in {}",
+                name,
+                location,
+                other,
+                CONFIG_FILE_NAME
+            );
+        }
+        type_defs.insert(name.clone(), def.clone());
+    }
     
     println!("Collected {} type definitions", type_defs.len());
     Ok(type_defs)
 }
 
 // Find all relevant Rust projects
-fn find_rust_projects(base_dir: &Path) -> Vec<PathBuf> {
+fn find_rust_projects(base_dir: &Path) -> Result<Vec<PathBuf>> {
     let mut projects = Vec::new();
     println!("Scanning for Rust projects in {}", base_dir.display());
     
     for entry in WalkDir::new(base_dir)
         .max_depth(1)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
     {
@@ -294,58 +1444,81 @@ fn find_rust_projects(base_dir: &Path) -> Vec<PathBuf> {
             println!("Checking {}", cargo_toml.display());
             
             if cargo_toml.exists() {
-                // Try to read and parse Cargo.toml
-                if let Ok(content) = fs::read_to_string(&cargo_toml) {
-                    if let Ok(cargo_data) = content.parse::<Value>() {
-                        // Check for the specific metadata
-                        if let Some(metadata) = cargo_data
-                            .get("package")
-                            .and_then(|p| p.get("metadata"))
-                            .and_then(|m| m.get("component"))
-                        {
-                            if let Some(package) = metadata.get("package") {
-                                if let Some(package_str) = package.as_str() {
-                                    println!("  Found package.metadata.component.package = {:?}", package_str);
-                                    if package_str == "hyperware:process" {
-                                        println!("  Adding project: {}", path.display());
-                                        projects.push(path.to_path_buf());
-                                    }
-                                }
+                // A Cargo.toml that can't be read may belong to a process, whose generated files
+                // would then be removed as stale
+                let content = fs::read_to_string(&cargo_toml)
+                    .with_context(|| format!("Failed to read {}", cargo_toml.display()))?;
+                let cargo_data = content
+                    .parse::<Value>()
+                    .with_context(|| format!("Failed to parse {}", cargo_toml.display()))?;
+                // Check for the specific metadata
+                if let Some(metadata) = cargo_data
+                    .get("package")
+                    .and_then(|p| p.get("metadata"))
+                    .and_then(|m| m.get("component"))
+                {
+                    if let Some(package) = metadata.get("package") {
+                        if let Some(package_str) = package.as_str() {
+                            println!("  Found package.metadata.component.package = {:?}", package_str);
+                            if package_str == "hyperware:process" {
+                                println!("  Adding project: {}", path.display());
+                                projects.push(path.to_path_buf());
                             }
-                        } else {
-                            println!("  No package.metadata.component metadata found");
                         }
                     }
+                } else {
+                    println!("  No package.metadata.component metadata found");
                 }
             }
         }
     }
     
     println!("Found {} relevant Rust projects", projects.len());
-    projects
+    Ok(projects)
 }
 
+// Comment prefix recording the Rust method name of a signature struct
+pub const RUST_NAME_COMMENT: &str = "Rust name: ";
+
+// Comment prefix recording the Cargo feature a signature struct's handler is gated behind
+pub const FEATURE_COMMENT: &str = "Feature: ";
+
 // Helper function to generate signature struct for specific attribute type
 fn generate_signature_struct(
     kebab_name: &str,
     attr_type: &str,
     method: &syn::ImplItemFn,
+    ctx: &mut TypeContext,
     used_types: &mut HashSet<String>,
 ) -> Result<String> {
     // Create signature struct name with attribute type
-    let signature_struct_name = format!("{}-signature-{}", kebab_name, attr_type);
+    let signature_struct_name = format!("{}-signature-{}", unescape(kebab_name), attr_type);
     
-    // Generate comment for this specific function
-    let comment = format!("    // Function signature for: {} ({})", kebab_name, attr_type);
+    // Generate comment for this specific function. The Rust name is recorded so caller stubs
+    // can use the exact request variant the hyperprocess macro derives from it.
+    let mut comment = format!(
+        "    // Function signature for: {} ({})\n    // {}{}",
+        kebab_name,
+        attr_type,
+        RUST_NAME_COMMENT,
+        method.sig.ident.unraw()
+    );
+    // Stubs of handlers behind a feature are gated behind the same feature of caller-utils
+    if let Some(feature) = feature_gate(&method.attrs) {
+        comment.push_str(&format!("\n    // {}{}", FEATURE_COMMENT, feature));
+    }
     
     // Create struct fields that directly represent function parameters
     let mut struct_fields = Vec::new();
+    // Parameter names, which can't repeat or shadow the `target` and `returning` fields
+    let mut field_names: HashSet<String> = ["target", "returning"].iter().map(|f| f.to_string()).collect();
     
     // Add target parameter based on attribute type
     if attr_type == "http" {
         struct_fields.push("        target: string".to_string());
     } else { // remote or local
         struct_fields.push("        target: address".to_string());
+        used_types.insert("address".to_string());
     }
     
     // Process function parameters (skip &self and &mut self)
@@ -357,16 +1530,24 @@ fn generate_signature_struct(
                     continue;
                 }
                 
-                // Get original param name and convert to kebab-case
-                let param_orig_name = pat_ident.ident.to_string();
-                
-                // Validate parameter name
-                validate_name(&param_orig_name, "Parameter")?;
-                
-                let param_name = to_kebab_case(&param_orig_name);
+                // Get original param name and convert to a kebab-case WIT identifier
+                let param_orig_name = pat_ident.ident.unraw().to_string();
+                let param_name = wit_identifier(&param_orig_name, "Parameter", &ctx.naming)?;
+                if !field_names.insert(param_name.clone()) {
+                    anyhow::bail!(
+                        "Error: parameter '{}' of method '{}' at {} maps to the WIT field '{}', which is \
+                         already used in its signature struct; rename it or give it a different WIT name \
+                         under [naming.renames] in {}",
+                        param_orig_name,
+                        method.sig.ident,
+                        ctx.location(pat_ident.ident.span()),
+                        param_name,
+                        CONFIG_FILE_NAME
+                    );
+                }
                 
                 // Rust type to WIT type
-                let param_type = rust_type_to_wit(&pat_type.ty, used_types)?;
+                let param_type = rust_type_to_wit(&pat_type.ty, ctx, used_types)?;
                 
                 // Add field directly to the struct
                 struct_fields.push(format!("        {}: {}", param_name, param_type));
@@ -377,19 +1558,24 @@ fn generate_signature_struct(
     // Add return type field
     match &method.sig.output {
         syn::ReturnType::Type(_, ty) => {
-            let return_type = rust_type_to_wit(&*ty, used_types)?;
+            let return_type = rust_type_to_wit(ty, ctx, used_types)?;
             struct_fields.push(format!("        returning: {}", return_type));
         }
         _ => {
             // For unit return type
-            struct_fields.push("        returning: unit".to_string());
+            struct_fields.push("        returning: tuple<>".to_string());
         }
     }
     
-    // Combine everything into a record definition
+    // Combine everything into a record definition, documented and gated like the handler
+    let stability = stability_wit(
+        &method.attrs, "    ", true, &format!("method '{}'", method.sig.ident), method.sig.ident.span(), ctx
+    )?;
     let record_def = format!(
-        "{}\n    record {} {{\n{}\n    }}",
+        "{}\n{}{}    record {} {{\n{}\n    }}",
         comment,
+        doc_comment(&method.attrs, "    "),
+        stability,
         signature_struct_name,
         struct_fields.join(",\n")
     );
@@ -397,24 +1583,114 @@ fn generate_signature_struct(
     Ok(record_def)
 }
 
-// Generate WIT content for an interface
-fn generate_interface_wit_content(
+// Describe how a type was reached from a signature, e.g. `get-signature-remote -> order -> item`
+fn reference_chain(type_name: &str, needed_by: &HashMap<String, String>) -> String {
+    let mut chain = vec![type_name.to_string()];
+    let mut current = type_name;
+    while let Some(parent) = needed_by.get(current) {
+        chain.push(parent.clone());
+        current = parent;
+    }
+    chain.reverse();
+    chain.join(" -> ")
+}
+
+// Find a cycle in the references between the given types, returned as the path around it
+// starting and ending with the same type
+fn find_type_cycle(type_defs: &[(String, TypeDef)]) -> Option<Vec<String>> {
+    let defs: HashMap<&str, &TypeDef> = type_defs.iter().map(|(name, def)| (name.as_str(), def)).collect();
+    let mut done = HashSet::new();
+    for (name, _) in type_defs {
+        let mut path = Vec::new();
+        if let Some(cycle) = visit_type_for_cycle(name, &defs, &mut path, &mut done) {
+            return Some(cycle);
+        }
+    }
+    None
+}
+
+// Depth-first search step for find_type_cycle
+fn visit_type_for_cycle<'a>(
+    name: &'a str,
+    defs: &HashMap<&'a str, &'a TypeDef>,
+    path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+) -> Option<Vec<String>> {
+    if let Some(start) = path.iter().position(|t| *t == name) {
+        let mut cycle: Vec<String> = path[start..].iter().map(|t| t.to_string()).collect();
+        cycle.push(name.to_string());
+        return Some(cycle);
+    }
+    if done.contains(name) {
+        return None;
+    }
+    let def = defs.get(name)?;
+
+    path.push(name);
+    for referenced_type in &def.references {
+        if let Some(cycle) = visit_type_for_cycle(referenced_type, defs, path, done) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    done.insert(name);
+    None
+}
+
+// Order type definitions so each comes after the types it references, ties broken by name
+fn topological_order(type_defs: Vec<(String, TypeDef)>) -> Vec<(String, TypeDef)> {
+    let mut remaining: BTreeMap<String, TypeDef> = type_defs.into_iter().collect();
+    let mut ordered = Vec::new();
+
+    while !remaining.is_empty() {
+        let next = remaining
+            .iter()
+            .find(|(_, def)| def.references.iter().all(|r| !remaining.contains_key(r)))
+            .map(|(name, _)| name.clone());
+
+        match next {
+            Some(name) => {
+                let def = remaining.remove(&name).unwrap();
+                ordered.push((name, def));
+            }
+            None => {
+                // Only reachable with a cycle, which has been rejected by now
+                ordered.extend(remaining);
+                break;
+            }
+        }
+    }
+
+    ordered
+}
+
+// Signature structs of an interface and the type definitions they need
+struct ConvertedInterface {
+    ctx: TypeContext,
+    signature_structs: Vec<String>,
+    // Types referenced directly by the signature structs
+    signature_refs: BTreeSet<String>,
+    // Used types in the order they were reached from the signatures
+    type_defs: Vec<(String, TypeDef)>,
+}
+
+// Convert the signatures of an interface and the transitive closure of the types they use
+fn convert_interface(
     impl_item: &syn::ItemImpl,
     interface_name: &str,
     ast: &syn::File,
-) -> Result<String> {
+    source_file: &Path,
+    config: &Config,
+    recursive_fields: &HashSet<(String, String)>,
+) -> Result<ConvertedInterface> {
     let mut signature_structs = Vec::new();
-    let mut used_types = HashSet::new();
-    
-    // Extract the base name without "State" suffix for the interface
-    let base_name = remove_state_suffix(interface_name);
-    
-    // Convert interface name to kebab-case for the interface declaration
-    let kebab_interface_name = to_kebab_case(&base_name);
-    println!("Generating WIT content for interface: {} (kebab: {})", interface_name, kebab_interface_name);
-    
-    // We'll use a simpler comment approach now, added directly in the content generation code
-    
+    // Types referenced directly by each signature struct, in source order
+    let mut signature_types: Vec<(String, HashSet<String>)> = Vec::new();
+    let mut ctx = TypeContext::new(ast, source_file, config);
+    ctx.recursive_fields = recursive_fields.clone();
+    // Rust method and location each WIT function name comes from
+    let mut functions = HashMap::new();
+
     for item in &impl_item.items {
         if let ImplItem::Fn(method) = item {
             let method_name = method.sig.ident.to_string();
@@ -429,33 +1705,38 @@ fn generate_interface_wit_content(
                 println!("    Has relevant attributes: remote={}, local={}, http={}", 
                     has_remote, has_local, has_http);
                 
-                // Validate function name
-                validate_name(&method_name, "Function")?;
-                
-                // Convert function name to kebab-case
-                let kebab_name = to_kebab_case(&method_name);
+                // Convert function name to a kebab-case WIT identifier
+                let kebab_name = wit_identifier(&method_name, "Function", &ctx.naming)?;
                 println!("    Processing method: {} -> {}", method_name, kebab_name);
+                let location = ctx.location(method.sig.ident.span());
+                claim_wit_name(&mut functions, &kebab_name, "methods", &method_name, &location)?;
                 
                 // Generate a signature struct for each attribute type
                 if has_remote {
+                    let mut used_types = HashSet::new();
                     let remote_struct = generate_signature_struct(
-                        &kebab_name, "remote", method, &mut used_types
+                        &kebab_name, "remote", method, &mut ctx, &mut used_types
                     )?;
                     signature_structs.push(remote_struct);
+                    signature_types.push((format!("{}-signature-remote", unescape(&kebab_name)), used_types));
                 }
                 
                 if has_local {
+                    let mut used_types = HashSet::new();
                     let local_struct = generate_signature_struct(
-                        &kebab_name, "local", method, &mut used_types
+                        &kebab_name, "local", method, &mut ctx, &mut used_types
                     )?;
                     signature_structs.push(local_struct);
+                    signature_types.push((format!("{}-signature-local", unescape(&kebab_name)), used_types));
                 }
                 
                 if has_http {
+                    let mut used_types = HashSet::new();
                     let http_struct = generate_signature_struct(
-                        &kebab_name, "http", method, &mut used_types
+                        &kebab_name, "http", method, &mut ctx, &mut used_types
                     )?;
                     signature_structs.push(http_struct);
+                    signature_types.push((format!("{}-signature-http", unescape(&kebab_name)), used_types));
                 }
             } else {
                 println!("    Skipping method without relevant attributes");
@@ -464,65 +1745,339 @@ fn generate_interface_wit_content(
     }
     
     // Collect all type definitions from the file
-    let all_type_defs = collect_type_definitions(ast)?;
+    let all_type_defs = collect_type_definitions(ast, &mut ctx)?;
+
+    // Signature structs share the namespace of the interface's types
+    for (signature, _) in &signature_types {
+        if all_type_defs.contains_key(signature) {
+            let location = ctx.type_sources.get(signature).map(String::as_str).unwrap_or("unknown location");
+            anyhow::bail!(
+                "Error: type at {} collides with the signature struct '{}'; \
+                 give it a different WIT name under [naming.renames] in {}",
+                location,
+                signature,
+                CONFIG_FILE_NAME
+            );
+        }
+
+        // Signature structs are traced back to their handler method
+        let handler = signature.rsplit_once("-signature-").and_then(|(function, _)| {
+            functions.iter().find(|(kebab, _)| unescape(kebab) == function)
+        });
+        if let Some((_, (_, location))) = handler {
+            ctx.type_sources.insert(signature.clone(), location.clone());
+        }
+    }
     
-    // Filter for only the types we're using
+    // Walk the reference graph from the signatures, remembering who needed each type
     let mut type_defs = Vec::new();
-    let mut processed_types = HashSet::new();
-    let mut types_to_process: Vec<String> = used_types.into_iter().collect();
-    
+    let mut needed_by: HashMap<String, String> = HashMap::new();
+    let mut types_to_process: VecDeque<String> = VecDeque::new();
+    for (signature, used_types) in &signature_types {
+        let mut used_types: Vec<&String> = used_types.iter().collect();
+        used_types.sort();
+        for type_name in used_types {
+            if !needed_by.contains_key(type_name) {
+                needed_by.insert(type_name.clone(), signature.clone());
+                types_to_process.push_back(type_name.clone());
+            }
+        }
+    }
+
     println!("Processing used types: {:?}", types_to_process);
-    
-    // Process all referenced types and their dependencies
-    while let Some(type_name) = types_to_process.pop() {
-        if processed_types.contains(&type_name) {
+
+    while let Some(type_name) = types_to_process.pop_front() {
+        println!("  Processing type: {}", type_name);
+
+        if is_standard_type(&type_name) && !all_type_defs.contains_key(&type_name) {
+            // Used from the `standard` interface
             continue;
         }
-        
-        processed_types.insert(type_name.clone());
-        println!("  Processing type: {}", type_name);
-        
+
         if let Some(type_def) = all_type_defs.get(&type_name) {
-            println!("    Found type definition");
-            type_defs.push(type_def.clone());
-            
-            // Extract any types referenced in this type definition
-            for referenced_type in all_type_defs.keys() {
-                if type_def.contains(referenced_type) && !processed_types.contains(referenced_type) {
+            type_defs.push((type_name.clone(), type_def.clone()));
+
+            for referenced_type in &type_def.references {
+                if !needed_by.contains_key(referenced_type) {
                     println!("    Adding referenced type: {}", referenced_type);
-                    types_to_process.push(referenced_type.clone());
+                    needed_by.insert(referenced_type.clone(), type_name.clone());
+                    types_to_process.push_back(referenced_type.clone());
                 }
             }
         } else {
-            println!("    No definition found for type: {}", type_name);
+            let chain = reference_chain(&type_name, &needed_by);
+            if let Some(e) = ctx.type_errors.remove(&type_name) {
+                return Err(e.context(format!(
+                    "Failed to convert type '{}' used by interface {} ({})",
+                    type_name, interface_name, chain
+                )));
+            }
+            anyhow::bail!(
+                "Error: type '{}' used by interface {} is not defined in the project ({})",
+                type_name, interface_name, chain
+            );
         }
     }
-    
-    // Generate the final WIT content
+
+    let signature_refs = signature_types.into_iter().flat_map(|(_, refs)| refs).collect();
+    Ok(ConvertedInterface { ctx, signature_structs, signature_refs, type_defs })
+}
+
+// Generate WIT content for an interface
+fn generate_interface_wit_content(
+    impl_item: &syn::ItemImpl,
+    interface_name: &str,
+    ast: &syn::File,
+    source_file: &Path,
+    config: &Config,
+) -> Result<Option<InterfaceWit>> {
+    // Convert interface name (without the "State" suffix) to a kebab-case WIT identifier
+    let kebab_interface_name = interface_wit_name(interface_name, &config.naming)?;
+    println!("Generating WIT content for interface: {} (kebab: {})", interface_name, kebab_interface_name);
+
+    // WIT types can't be recursive. With a fallback configured, the reference closing each
+    // cycle is encoded as the fallback type and the interface converted again.
+    let mut recursive_fields = HashSet::new();
+    let ConvertedInterface { ctx, signature_structs, signature_refs, type_defs } = loop {
+        let converted = convert_interface(
+            impl_item, interface_name, ast, source_file, config, &recursive_fields
+        )?;
+        let Some(cycle) = find_type_cycle(&converted.type_defs) else {
+            break converted;
+        };
+
+        let closing = (cycle[cycle.len() - 2].clone(), cycle[cycle.len() - 1].clone());
+        if config.recursive_fallback.is_some() && recursive_fields.insert(closing) {
+            println!("Breaking recursive cycle {} with the configured fallback", cycle.join(" -> "));
+            continue;
+        }
+
+        let locations: String = cycle[..cycle.len() - 1]
+            .iter()
+            .map(|t| {
+                let source = converted.ctx.type_sources.get(t).map(String::as_str).unwrap_or("unknown location");
+                format!("\n    {} is declared at {}", t, source)
+            })
+            .collect();
+        anyhow::bail!(
+            "Error: recursive type in interface {}: {}; WIT does not allow recursive types.{}\n\
+             Set `recursive-fallback = \"string\"` under [types] in {} to encode the field closing \
+             the cycle as a JSON string instead",
+            interface_name,
+            cycle.join(" -> "),
+            locations,
+            CONFIG_FILE_NAME
+        );
+    };
     if signature_structs.is_empty() {
         println!("No functions found for interface {}", interface_name);
-        Ok(String::new())
-    } else {
-        // Start with a simplified interface comment
-        let mut content = "    // This interface contains function signature definitions that will be used\n    // by the hyper-bindgen macro to generate async function bindings.\n    //\n    // NOTE: This is currently a hacky workaround since WIT async functions are not\n    // available until WASI Preview 3. Once Preview 3 is integrated into Hyperware,\n    // we should switch to using proper async WIT function signatures instead of\n    // this struct-based approach with hyper-bindgen generating the async stubs.\n".to_string();
-        
-        // Add standard imports
-        content.push_str("\n    use standard.{address};\n\n");
-        
+        return Ok(None);
+    }
+
+    println!("Generated interface content for {} with {} signature structs", interface_name, signature_structs.len());
+    Ok(Some(InterfaceWit {
+        name: kebab_interface_name,
+        signature_refs,
+        type_defs: topological_order(type_defs),
+        signature_structs,
+        shared_uses: BTreeSet::new(),
+        sources: ctx.type_sources.into_iter().collect(),
+    }))
+}
+
+// WIT of one interface, kept apart until types shared with other interfaces are split off
+struct InterfaceWit {
+    // WIT name of the interface
+    name: String,
+    // Types referenced directly by the signature structs
+    signature_refs: BTreeSet<String>,
+    // Type definitions, dependencies first
+    type_defs: Vec<(String, TypeDef)>,
+    signature_structs: Vec<String>,
+    // Types used from the shared types interface
+    shared_uses: BTreeSet<String>,
+    // Rust item each type and signature struct was generated from, for diagnostics
+    sources: BTreeMap<String, String>,
+}
+
+impl InterfaceWit {
+    fn file_name(&self) -> String {
+        format!("{}.wit", unescape(&self.name))
+    }
+
+    // The interface file, declared in `package` and using standard types from `hyperware`
+    fn to_wit(&self, package: &PackageId, hyperware: &PackageId) -> String {
+        let mut content = if self.signature_structs.is_empty() {
+            format!("    {}\n    // callers get a single Rust type for each.\n", SHARED_TYPES_INTRO)
+        } else {
+            // Start with a simplified interface comment
+            format!("    {}\n    // by the hyper-bindgen macro to generate async function bindings.\n    //\n    // NOTE: This is currently a hacky workaround since WIT async functions are not\n    // available until WASI Preview 3. Once Preview 3 is integrated into Hyperware,\n    // we should switch to using proper async WIT function signatures instead of\n    // this struct-based approach with hyper-bindgen generating the async stubs.\n", SIGNATURES_INTRO)
+        };
+
+        // Standard types referenced by what is left in this interface
+        let defined: HashSet<&String> = self.type_defs.iter().map(|(name, _)| name).collect();
+        let standard_types: BTreeSet<&String> = self
+            .signature_refs
+            .iter()
+            .chain(self.type_defs.iter().flat_map(|(_, def)| &def.references))
+            .filter(|r| is_standard_type(r) && !defined.contains(r) && !self.shared_uses.contains(*r))
+            .collect();
+
+        // Add standard and shared imports
+        if !standard_types.is_empty() || !self.shared_uses.is_empty() {
+            content.push('\n');
+        }
+        if !standard_types.is_empty() {
+            let standard_types: Vec<&str> = standard_types.iter().map(|t| t.as_str()).collect();
+            content.push_str(&format!("    use {}.{{{}}};\n", hyperware.item("standard"), standard_types.join(", ")));
+        }
+        if !self.shared_uses.is_empty() {
+            let shared_uses: Vec<&str> = self.shared_uses.iter().map(String::as_str).collect();
+            content.push_str(&format!("    use {}.{{{}}};\n", SHARED_TYPES_INTERFACE, shared_uses.join(", ")));
+        }
+        content.push('\n');
+
         // Add type definitions if any
+        let type_defs: Vec<&str> = self.type_defs.iter().map(|(_, def)| def.wit.as_str()).collect();
         if !type_defs.is_empty() {
             content.push_str(&type_defs.join("\n\n"));
-            content.push_str("\n\n");
+            if !self.signature_structs.is_empty() {
+                content.push_str("\n\n");
+            }
         }
-        
+
         // Add signature structs
-        content.push_str(&signature_structs.join("\n\n"));
-        
+        content.push_str(&self.signature_structs.join("\n\n"));
+
         // Wrap in interface block
-        let final_content = format!("interface {} {{\n{}\n}}\n", kebab_interface_name, content);
-        println!("Generated interface content for {} with {} signature structs", interface_name, signature_structs.len());
-        Ok(final_content)
+        format!("package {};\n\ninterface {} {{\n{}\n}}\n", package, self.name, content)
+    }
+}
+
+// World generated for the caller-utils crate, importing every interface
+pub const CALLER_UTILS_WORLD: &str = "caller-utils";
+
+// World of the hyperware:process package every process world includes
+const PROCESS_WORLD: &str = "process-v1";
+
+// First lines of the comments opening the interfaces hyper-bindgen generates
+const SIGNATURES_INTRO: &str = "// This interface contains function signature definitions that will be used";
+const SHARED_TYPES_INTRO: &str = "// Types defined identically by several interfaces, which use them from here so";
+
+// Name of the interface defined by a WIT file hyper-bindgen generated
+pub fn generated_interface_name(content: &str) -> Option<String> {
+    let mut lines = content
+        .lines()
+        .skip_while(|l| l.is_empty() || l.starts_with("//") || l.starts_with("package "));
+    let name = lines.next()?.strip_prefix("interface ")?.strip_suffix(" {")?;
+    let intro = lines.next()?.trim();
+    (intro == SIGNATURES_INTRO || intro == SHARED_TYPES_INTRO).then(|| name.to_string())
+}
+
+// The types of all interfaces end up in one module of the caller-utils crate, so a type name
+// several interfaces define must have the same WIT everywhere
+fn check_type_clashes(interfaces: &[InterfaceWit]) -> Result<()> {
+    let mut first: HashMap<&String, (&InterfaceWit, &TypeDef)> = HashMap::new();
+    for interface in interfaces {
+        for (name, def) in &interface.type_defs {
+            let Some((other, other_def)) = first.get(name) else {
+                first.insert(name, (interface, def));
+                continue;
+            };
+            if other_def.wit != def.wit {
+                let source = |i: &InterfaceWit| i.sources.get(name).cloned().unwrap_or_else(|| "?".to_string());
+                anyhow::bail!(
+                    "Error: type '{}' is defined differently in interface {} (from the Rust item at {}) \
+                     and in interface {} (from the Rust item at {}); callers get a single Rust type \
+                     for each name, so rename one of them",
+                    name,
+                    other.name,
+                    source(other),
+                    interface.name,
+                    source(interface)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+// Interface holding the types several process interfaces define identically
+pub const SHARED_TYPES_INTERFACE: &str = "types";
+
+// Move type definitions that several interfaces share into a common interface. A type is
+// shared if at least two interfaces define it with the same WIT and every type it references
+// is shared as well. Types defined differently under one name are rejected before this by
+// `check_type_clashes`.
+fn extract_shared_types(interfaces: &mut [InterfaceWit]) -> Option<InterfaceWit> {
+    let mut definitions: BTreeMap<&String, Vec<&TypeDef>> = BTreeMap::new();
+    for interface in interfaces.iter() {
+        for (name, def) in &interface.type_defs {
+            definitions.entry(name).or_default().push(def);
+        }
+    }
+
+    let mut shared: BTreeMap<String, TypeDef> = definitions
+        .into_iter()
+        .filter(|(_, defs)| defs.len() > 1 && defs.iter().all(|def| def.wit == defs[0].wit))
+        .map(|(name, defs)| (name.clone(), defs[0].clone()))
+        .collect();
+
+    // Drop types referencing ones that differ between interfaces, until nothing changes
+    loop {
+        let unshareable: Vec<String> = shared
+            .iter()
+            .filter(|(_, def)| {
+                def.references
+                    .iter()
+                    .any(|r| !shared.contains_key(r) && !is_standard_type(r))
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        if unshareable.is_empty() {
+            break;
+        }
+        for name in unshareable {
+            shared.remove(&name);
+        }
     }
+
+    if shared.is_empty() {
+        return None;
+    }
+
+    // Diagnostics for a shared type point at its first declaration
+    let mut sources = BTreeMap::new();
+    for interface in interfaces.iter() {
+        for name in shared.keys() {
+            if let Some(source) = interface.sources.get(name) {
+                sources.entry(name.clone()).or_insert_with(|| source.clone());
+            }
+        }
+    }
+
+    for interface in interfaces.iter_mut() {
+        let (moved, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut interface.type_defs)
+            .into_iter()
+            .partition(|(name, _)| shared.contains_key(name));
+        interface.type_defs = kept;
+        interface.shared_uses = moved.into_iter().map(|(name, _)| name).collect();
+        if !interface.shared_uses.is_empty() {
+            println!(
+                "Interface {} uses shared types: {:?}",
+                interface.name, interface.shared_uses
+            );
+        }
+    }
+
+    Some(InterfaceWit {
+        name: SHARED_TYPES_INTERFACE.to_string(),
+        signature_refs: BTreeSet::new(),
+        type_defs: topological_order(shared.into_iter().collect()),
+        signature_structs: Vec::new(),
+        shared_uses: BTreeSet::new(),
+        sources,
+    })
 }
 
 // Helper trait to get TypePath from Type
@@ -539,29 +2094,38 @@ impl AsTypePath for syn::Type {
     }
 }
 
-// Process a single Rust project and generate WIT files
-fn process_rust_project(project_path: &Path, api_dir: &Path) -> Result<Option<String>> {
+// Process a single Rust project and collect the WIT of its interfaces
+fn process_rust_project(
+    project_path: &Path,
+    config: &Config,
+    interfaces_seen: &mut HashMap<String, (String, String)>,
+    generated: &mut Vec<InterfaceWit>,
+) -> Result<Vec<(String, String)>> {
     println!("\nProcessing project: {}", project_path.display());
     let lib_rs = project_path.join("src").join("lib.rs");
     
     println!("Looking for lib.rs at {}", lib_rs.display());
+    // Without its lib.rs the interfaces of the process are unknown, and carrying on would remove
+    // the files generated for them earlier
     if !lib_rs.exists() {
-        println!("No lib.rs found for project: {}", project_path.display());
-        return Ok(None);
+        anyhow::bail!("Error: no lib.rs found for project: {}", project_path.display());
     }
     
     let lib_content = fs::read_to_string(&lib_rs)
         .with_context(|| format!("Failed to read lib.rs for project: {}", project_path.display()))?;
     
     println!("Successfully read lib.rs, parsing...");
-    let ast = syn::parse_file(&lib_content)
+    let mut ast = syn::parse_file(&lib_content)
         .with_context(|| format!("Failed to parse lib.rs for project: {}", project_path.display()))?;
     
     println!("Successfully parsed lib.rs");
     
-    let mut wit_world = None;
-    let mut interface_name = None;
-    let mut kebab_interface_name = None;
+    // Only what the active features and cfg options compile in is part of the interface
+    apply_cfg(&mut ast, &ActiveCfg::for_project(project_path, &config.cfg)?)
+        .with_context(|| format!("Failed to evaluate #[cfg] in lib.rs for project: {}", project_path.display()))?;
+    
+    // World and name of each interface generated for the project
+    let mut project_interfaces = Vec::new();
     
     println!("Scanning for impl blocks with hyperprocess attribute");
     for item in &ast.items {
@@ -573,13 +2137,13 @@ fn process_rust_project(project_path: &Path, api_dir: &Path) -> Result<Option<St
                 println!("Found hyperprocess attribute");
                 
                 // Extract the wit_world name
-                match extract_wit_world(&[attr.clone()]) {
+                match extract_wit_world(std::slice::from_ref(attr)) {
                     Ok(world_name) => {
                         println!("Extracted wit_world: {}", world_name);
-                        wit_world = Some(world_name);
+                        let wit_world = Some(world_name);
                         
                         // Get the interface name from the impl type
-                        interface_name = impl_item
+                        let interface_name = impl_item
                             .self_ty
                             .as_ref()
                             .as_type_path()
@@ -592,15 +2156,13 @@ fn process_rust_project(project_path: &Path, api_dir: &Path) -> Result<Option<St
                             });
                         
                         // Check for "State" suffix and remove it
+                        let mut kebab_interface_name = None;
                         if let Some(ref name) = interface_name {
-                            // Validate the interface name
-                            validate_name(name, "Interface")?;
-                            
                             // Remove State suffix if present
                             let base_name = remove_state_suffix(name);
                             
-                            // Convert to kebab-case for file name and interface name
-                            kebab_interface_name = Some(to_kebab_case(&base_name));
+                            // Convert to a kebab-case WIT identifier for file name and interface name
+                            kebab_interface_name = Some(interface_wit_name(name, &config.naming)?);
                             
                             println!("Interface name: {:?}", interface_name);
                             println!("Base name: {}", base_name);
@@ -608,155 +2170,564 @@ fn process_rust_project(project_path: &Path, api_dir: &Path) -> Result<Option<St
                         }
                         
                         if let (Some(ref iface_name), Some(ref kebab_name)) = (&interface_name, &kebab_interface_name) {
-                            // We already validated the interface name, so the file name should be fine
+                            // Interfaces of all projects share the api directory and the world
+                            let location = span_location(&lib_rs.display().to_string(), impl_item.self_ty.span());
+                            claim_wit_name(interfaces_seen, kebab_name, "impl blocks", iface_name, &location)?;
+                            if Some(kebab_name) == wit_world.as_ref() {
+                                anyhow::bail!(
+                                    "Error: interface '{}' of {} at {} has the same name as its world, \
+                                     so its WIT file would replace the world definition",
+                                    kebab_name,
+                                    iface_name,
+                                    location
+                                );
+                            }
                             
                             // Generate the WIT content
-                            let content = generate_interface_wit_content(impl_item, iface_name, &ast)?;
-                            
-                            if !content.is_empty() {
-                                // Write the interface file with kebab-case name
-                                let interface_file = api_dir.join(format!("{}.wit", kebab_name));
-                                println!("Writing WIT file to {}", interface_file.display());
-                                
-                                fs::write(&interface_file, &content)
-                                    .with_context(|| format!("Failed to write {}", interface_file.display()))?;
-                                
-                                println!("Successfully wrote WIT file");
-                            } else {
-                                println!("Generated WIT content is empty, skipping file creation");
+                            // Files are written once all projects are processed and shared types are known
+                            match generate_interface_wit_content(impl_item, iface_name, &ast, &lib_rs, config)? {
+                                Some(interface) => {
+                                    if let Some(world) = &wit_world {
+                                        project_interfaces.push((world.clone(), kebab_name.clone()));
+                                    }
+                                    generated.push(interface);
+                                }
+                                None => println!("Generated WIT content is empty, skipping file creation"),
                             }
                         }
                     },
-                    Err(e) => println!("Failed to extract wit_world: {}", e),
+                    Err(e) => return Err(e.context(format!(
+                        "Failed to extract wit_world of the hyperprocess impl in {}",
+                        lib_rs.display()
+                    ))),
                 }
             }
         }
     }
     
-    if let (Some(_), Some(_), Some(kebab_iface)) = (wit_world, interface_name, kebab_interface_name) {
-        println!("Returning import statement for interface {}", kebab_iface);
-        // Use kebab-case interface name for import
-        Ok(Some(format!("    import {};", kebab_iface)))
-    } else {
+    if project_interfaces.is_empty() {
         println!("No valid interface found");
-        Ok(None)
+    }
+    Ok(project_interfaces)
+}
+
+// Collects the caller-utils stub modules named by the paths, `use` trees and macro arguments of
+// a file. Comments and string literals aren't part of the syntax tree, so they never match.
+struct CallerUtilsPaths<'a> {
+    // Stub module names mapped to interface names
+    modules: &'a BTreeMap<String, String>,
+    // Names the caller-utils crate is known by in the file, e.g. after `use caller_utils as cu;`
+    crate_names: HashSet<String>,
+    called: BTreeSet<String>,
+}
+
+impl CallerUtilsPaths<'_> {
+    fn call(&mut self, module: &str) {
+        if let Some(interface) = self.modules.get(module) {
+            self.called.insert(interface.clone());
+        }
+    }
+
+    // `in_crate` is set for the part of a `use` tree following the crate name
+    fn use_tree(&mut self, tree: &syn::UseTree, in_crate: bool) {
+        match tree {
+            syn::UseTree::Path(path) if in_crate => self.call(&path.ident.to_string()),
+            syn::UseTree::Path(path) => {
+                let in_crate = self.crate_names.contains(&path.ident.to_string());
+                self.use_tree(&path.tree, in_crate);
+            }
+            syn::UseTree::Name(name) if in_crate => self.call(&name.ident.to_string()),
+            syn::UseTree::Rename(rename) if in_crate => self.call(&rename.ident.to_string()),
+            syn::UseTree::Rename(rename) if self.crate_names.contains(&rename.ident.to_string()) => {
+                self.crate_names.insert(rename.rename.to_string());
+            }
+            // `caller_utils::*` may use any of them
+            syn::UseTree::Glob(_) if in_crate => {
+                self.called.extend(self.modules.values().cloned());
+            }
+            syn::UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.use_tree(tree, in_crate);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Macro arguments aren't parsed, so look for `<crate>::<module>` in their tokens
+    fn tokens(&mut self, tokens: proc_macro2::TokenStream) {
+        use proc_macro2::TokenTree;
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        for (i, token) in tokens.iter().enumerate() {
+            match token {
+                TokenTree::Group(group) => self.tokens(group.stream()),
+                TokenTree::Ident(ident) if self.crate_names.contains(&ident.to_string()) => {
+                    if let [TokenTree::Punct(a), TokenTree::Punct(b), TokenTree::Ident(module), ..] = &tokens[i + 1..] {
+                        if a.as_char() == ':' && b.as_char() == ':' {
+                            self.call(&module.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl VisitMut for CallerUtilsPaths<'_> {
+    fn visit_item_use_mut(&mut self, item: &mut syn::ItemUse) {
+        self.use_tree(&item.tree, false);
+    }
+
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+        if let Some(index) = segments.iter().position(|s| self.crate_names.contains(s)) {
+            if let Some(module) = segments.get(index + 1) {
+                self.call(module);
+            }
+        }
+        visit_mut::visit_path_mut(self, path);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        self.tokens(mac.tokens.clone());
+        visit_mut::visit_macro_mut(self, mac);
+    }
+}
+
+// Interfaces a project calls through the caller-utils stubs, found in the `caller_utils::<module>`
+// paths of its parsed sources. `modules` maps stub module names to interface names.
+fn called_interfaces(project_path: &Path, modules: &BTreeMap<String, String>) -> Result<BTreeSet<String>> {
+    let mut paths = CallerUtilsPaths {
+        modules,
+        crate_names: HashSet::from(["caller_utils".to_string()]),
+        called: BTreeSet::new(),
+    };
+    for entry in WalkDir::new(project_path.join("src"))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+    {
+        let path = entry.path();
+        if !path.is_file() || path.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut ast = syn::parse_file(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        // A `use caller_utils as ...` only applies to the file it's in
+        paths.crate_names = HashSet::from(["caller_utils".to_string()]);
+        paths.visit_file_mut(&mut ast);
+    }
+    Ok(paths.called)
+}
+
+// Imports a world should have: those of the generated interfaces assigned to it, then the
+// configured ones
+fn wanted_imports(world: &str, world_imports: &BTreeMap<String, Vec<String>>, config: &Config) -> Vec<String> {
+    let mut wanted = world_imports.get(world).cloned().unwrap_or_default();
+    if let Some(world_config) = config.worlds.get(world) {
+        for import in &world_config.imports {
+            if !wanted.contains(import) {
+                wanted.push(import.clone());
+            }
+        }
+    }
+    wanted
+}
+
+// Error for a generated WIT package that doesn't resolve, naming the Rust item the offending
+// WIT was generated from where it can be traced back
+fn invalid_wit_error(
+    diagnostic: &str,
+    generated: &[InterfaceWit],
+    package_files: &BTreeMap<PathBuf, String>,
+    api_dir: &Path,
+) -> anyhow::Error {
+    // Stable items are resolved without any features, so they can't use gated types
+    let diagnostic = &if diagnostic.contains("excluded due to its feature not being activated") {
+        format!(
+            "{}\nA type behind an @unstable gate is used by an item that isn't behind it. Deprecated types \
+             are gated by `feature` under [deprecation] in {}; unset it, or stop using the type from \
+             stable items.",
+            diagnostic, CONFIG_FILE_NAME
+        )
+    } else {
+        diagnostic.to_string()
+    };
+    let located = diagnostic_location(diagnostic).and_then(|(path, line)| {
+        let interface = generated.iter().find(|i| api_dir.join(i.file_name()) == path)?;
+        let item = enclosing_item(package_files.get(&path)?, line);
+        Some((interface, item))
+    });
+
+    match located {
+        Some((interface, Some(item))) if interface.sources.contains_key(&item) => anyhow::anyhow!(
+            "Error: the WIT generated for '{}' in interface {} (from the Rust item at {}) is invalid:\n{}",
+            item,
+            interface.name,
+            interface.sources[&item],
+            diagnostic
+        ),
+        Some((interface, _)) => anyhow::anyhow!(
+            "Error: the WIT generated for interface {} is invalid:\n{}",
+            interface.name,
+            diagnostic
+        ),
+        None => anyhow::anyhow!(
+            "Error: the WIT package in {} doesn't resolve:\n{}",
+            api_dir.display(),
+            diagnostic
+        ),
     }
 }
 
+// Remove what earlier runs generated once nothing is generated anymore: the WIT files, the
+// caller-utils crate and the edits to worlds and Cargo.toml files
+fn remove_stale_output(base_dir: &Path, manifest: &mut Manifest, force: bool) -> Result<()> {
+    if *manifest == Manifest::default() {
+        return Ok(());
+    }
+    println!("Removing the files generated by earlier runs");
+    manifest.ensure_unmodified(base_dir, force)?;
+    manifest.remove_all(base_dir)
+}
+
 // Generate WIT files from Rust code
-pub fn generate_wit_files(base_dir: &Path, api_dir: &Path) -> Result<(Vec<PathBuf>, Vec<String>)> {
+pub fn generate_wit_files(
+    base_dir: &Path,
+    api_dir: &Path,
+    config: &Config,
+    manifest: &mut Manifest,
+    force: bool,
+) -> Result<(Vec<PathBuf>, Vec<String>)> {
     // Find all relevant Rust projects
-    let projects = find_rust_projects(base_dir);
+    let projects = find_rust_projects(base_dir)?;
     let mut processed_projects = Vec::new();
     
     if projects.is_empty() {
         println!("No relevant Rust projects found.");
+        remove_stale_output(base_dir, manifest, force)?;
         return Ok((Vec::new(), Vec::new()));
     }
     
-    // Process each project and collect world imports
-    let mut world_imports = Vec::new();
-    let mut world_names = HashSet::new();
+    // The generated files form a package; deprecated items are gated as available since its
+    // version unless configured otherwise
+    let package = resolve_package(config, api_dir, &projects)?;
+    let mut config = config.clone();
+    if config.deprecation.since.is_none() {
+        config.deprecation.since = package.version.clone();
+    }
+    let config = &config;
+
+    // Process each project and collect its world and interface
+    let mut project_interfaces: Vec<(PathBuf, String, String)> = Vec::new();
     let mut interfaces = Vec::new();
+    // Impl block and location each interface name comes from, across all projects
+    let mut interfaces_seen = HashMap::new();
+    // WIT of the interfaces of all projects
+    let mut generated = Vec::new();
     
     for project_path in &projects {
         println!("Processing project: {}", project_path.display());
         
-        match process_rust_project(project_path, api_dir) {
-            Ok(Some(import)) => {
-                println!("Got import statement: {}", import);
-                world_imports.push(import.clone());
-                
-                // Extract interface name from import statement
-                let interface_name = import
-                    .trim_start_matches("    import ")
-                    .trim_end_matches(";")
-                    .to_string();
-                
-                interfaces.push(interface_name);
-                processed_projects.push(project_path.clone());
-            },
-            Ok(None) => println!("No import statement generated"),
-            Err(e) => println!("Error processing project: {}", e),
+        // A project that fails stops the run, so nothing is written from a partial API
+        let found = process_rust_project(project_path, config, &mut interfaces_seen, &mut generated)
+            .with_context(|| format!("Failed to generate WIT for project {}", project_path.display()))?;
+        if found.is_empty() {
+            println!("No interfaces generated");
+            continue;
         }
+        for (world, interface_name) in found {
+            println!("Got interface {} for world {}", interface_name, world);
+            if world == CALLER_UTILS_WORLD {
+                anyhow::bail!(
+                    "Error: {} uses the world name '{}', which is reserved for the world of the caller-utils crate",
+                    project_path.display(),
+                    CALLER_UTILS_WORLD
+                );
+            }
+            project_interfaces.push((project_path.clone(), world, interface_name.clone()));
+            interfaces.push(interface_name);
+        }
+        processed_projects.push(project_path.clone());
     }
-    
-    println!("Collected {} world imports", world_imports.len());
-    
-    // Check for existing world definition files and update them
-    println!("Looking for existing world definition files");
+
+    // Types several interfaces define identically are emitted once, in a shared interface
+    check_type_clashes(&generated)?;
+    if let Some(shared) = extract_shared_types(&mut generated) {
+        if let Some((impl_name, location)) = interfaces_seen.get(SHARED_TYPES_INTERFACE) {
+            anyhow::bail!(
+                "Error: interface '{}' of {} at {} has the name of the interface holding shared types; \
+                 give it a different WIT name under [naming.renames] in {}",
+                SHARED_TYPES_INTERFACE,
+                impl_name,
+                location,
+                CONFIG_FILE_NAME
+            );
+        }
+        println!("Emitting {} shared types in interface {}", shared.type_defs.len(), SHARED_TYPES_INTERFACE);
+        generated.push(shared);
+    }
+
+    // Each world exports the interfaces of the processes using it and imports the ones they call.
+    // Processes sharing a world share its exports and imports.
+    let modules: BTreeMap<String, String> = project_interfaces
+        .iter()
+        .map(|(_, _, interface)| (to_rust_identifier(to_snake_case(unescape(interface))), interface.clone()))
+        .collect();
+    let mut world_projects: BTreeMap<String, BTreeSet<&PathBuf>> = BTreeMap::new();
+    let mut world_exports: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (project_path, world, interface) in &project_interfaces {
+        world_projects.entry(world.clone()).or_default().insert(project_path);
+        world_exports.entry(world.clone()).or_default().push(interface.clone());
+    }
+    let mut world_imports: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (world, project_paths) in &world_projects {
+        let exports = &world_exports[world];
+        let imports = world_imports.entry(world.clone()).or_default();
+        for project_path in project_paths {
+            for called in called_interfaces(project_path, &modules)? {
+                if exports.contains(&called) {
+                    // A world can't import what it exports; the stubs don't need the import
+                    println!("{} calls interface {} of its own world", project_path.display(), called);
+                } else if !imports.contains(&called) {
+                    println!("{} calls interface {}", project_path.display(), called);
+                    imports.push(called);
+                }
+            }
+        }
+    }
+    if generated.iter().any(|i| i.name == SHARED_TYPES_INTERFACE) {
+        for imports in world_imports.values_mut() {
+            imports.insert(0, SHARED_TYPES_INTERFACE.to_string());
+        }
+    }
+
+    // The caller-utils crate is generated from a world importing every interface
+    world_imports.insert(
+        CALLER_UTILS_WORLD.to_string(),
+        generated.iter().map(|i| i.name.clone()).collect(),
+    );
+
+    if processed_projects.is_empty() {
+        println!("No interfaces generated");
+        remove_stale_output(base_dir, manifest, force)?;
+        return Ok((processed_projects, interfaces));
+    }
+
+    // The package depends on hyperware:process from api/deps, written with the other files if missing
+    let (hyperware, hyperware_file) = hyperware_dep(api_dir)?;
+    let new_deps: BTreeMap<PathBuf, String> = hyperware_file.into_iter().collect();
+    let process_world = hyperware.item(PROCESS_WORLD);
+
+    // Existing files of the package
+    let mut existing = BTreeMap::new();
     for entry in WalkDir::new(api_dir)
         .max_depth(1)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
     {
         let path = entry.path();
-        
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "wit") {
-            println!("Checking WIT file: {}", path.display());
-            
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "wit") {
             if let Ok(content) = fs::read_to_string(path) {
-                if content.contains("world ") {
-                    println!("Found world definition file");
-                    
-                    // Extract the world name
-                    let lines: Vec<&str> = content.lines().collect();
-                    
-                    if let Some(world_line) = lines.iter().find(|line| line.trim().starts_with("world ")) {
-                        println!("World line: {}", world_line);
-                        
-                        if let Some(world_name) = world_line.trim().split_whitespace().nth(1) {
-                            let clean_name = world_name.trim_end_matches(" {");
-                            println!("Extracted world name: {}", clean_name);
-                            
-                            // We don't need to validate world names for digits
-                            
-                            world_names.insert(clean_name.to_string());
-                            
-                            // Create updated world content - use import
-                            let world_content = format!(
-                                "world {} {{\n{}\n    include process-v1;\n}}",
-                                clean_name,
-                                world_imports.join("\n") // No comma separator because each import has a semicolon
-                            );
-                            
-                            println!("Writing updated world definition to {}", path.display());
-                            // Write the updated world file
-                            fs::write(path, world_content)
-                                .with_context(|| format!("Failed to write updated world file: {}", path.display()))?;
-                            
-                            println!("Successfully updated world definition");
-                        }
-                    }
-                }
+                existing.insert(path.to_path_buf(), content);
             }
         }
     }
+
+    // Imports and exports hyper-bindgen owns: the interfaces generated now and by earlier runs.
+    // Other items in the world files are left alone.
+    let mut owned_imports: HashSet<String> = generated.iter().map(|i| i.name.clone()).collect();
+    owned_imports.extend(existing.values().filter_map(|content| generated_interface_name(content)));
+
+    // Every file of the package as it will be written, and the ones hyper-bindgen writes
+    let mut package_files = BTreeMap::new();
+    let mut to_write = BTreeSet::new();
+    // Files generated by earlier runs that nothing generates anymore
+    let mut orphans = Vec::new();
+    let created_before: HashSet<PathBuf> = manifest.files.iter().map(|f| base_dir.join(f)).collect();
+
+    let inputs = input_hash(base_dir, &processed_projects, &config.cfg);
+    for interface in &generated {
+        let interface_file = api_dir.join(interface.file_name());
+        package_files.insert(interface_file.clone(), with_wit_header(&interface.to_wit(&package, &hyperware), &inputs));
+        to_write.insert(interface_file);
+    }
     
-    // If no world definitions were found, create a default one
-    if world_names.is_empty() && !world_imports.is_empty() {
-        // Define default world name
-        let default_world = "async-app-template-dot-os-v0";
-        println!("No existing world definitions found, creating default with name: {}", default_world);
-        
-        // We don't need to validate world names for digits
+    println!("Collected imports for {} worlds", world_imports.len());
+    
+    // Update the imports of the worlds defined in existing WIT files
+    let mut worlds_found = HashSet::new();
+    println!("Looking for existing world definition files");
+    for (path, content) in existing {
+        let worlds = parse_worlds(&content);
+        if worlds.is_empty() {
+            if to_write.contains(&path) {
+                continue;
+            }
+            if generated_interface_name(&content).is_some() || created_before.contains(&path) {
+                println!("Found stale generated file {}", path.display());
+                orphans.push(path);
+            } else {
+                package_files.insert(path, content);
+            }
+            continue;
+        }
+        if created_before.contains(&path) && !worlds.iter().any(|w| world_imports.contains_key(&w.name)) {
+            println!("Found generated world file {} no process uses anymore", path.display());
+            orphans.push(path);
+            continue;
+        }
+        println!("Found world definitions in {}", path.display());
+
+        let original = content.clone();
+        let mut content = content;
+        for world in worlds {
+            println!("Updating imports and exports of world {}", world.name);
+            let wanted = wanted_imports(&world.name, &world_imports, config);
+            if let Some(updated) = update_world_items(&content, &world.name, "import", &wanted, &owned_imports) {
+                content = updated;
+            }
+            let exports = world_exports.get(&world.name).cloned().unwrap_or_default();
+            if let Some(updated) = update_world_items(&content, &world.name, "export", &exports, &owned_imports) {
+                content = updated;
+            }
+            if let Some(world_config) = config.worlds.get(&world.name) {
+                content = ensure_world_statements(&content, &world.name, &world_config.statements());
+            }
+            // `process-v1` lives in the hyperware:process dependency
+            content = replace_include(&content, &world.name, PROCESS_WORLD, &process_world);
+            worlds_found.insert(world.name);
+        }
+        content = set_package_decl(&content, &package, &path, created_before.contains(&path))?;
+        if content != original && !created_before.contains(&path) {
+            // The content before the first edit is what `clean` restores
+            manifest.edited_worlds.entry(Manifest::relative(base_dir, &path)).or_insert(original);
+        }
+        package_files.insert(path.clone(), content);
+        to_write.insert(path);
+    }
+    
+    // Create the worlds named by hyperprocess attributes that aren't defined yet
+    for world in world_imports.keys() {
+        if worlds_found.contains(world) {
+            continue;
+        }
+        println!("No definition found for world {}, creating one", world);
         
         // Create world content with process-v1 include
-        let world_content = format!(
-            "world {} {{\n{}\n    include process-v1;\n}}",
-            default_world,
-            world_imports.join("\n") // No comma separator because each import has a semicolon
+        let mut items: Vec<String> = wanted_imports(world, &world_imports, config)
+            .iter()
+            .map(|name| format!("    import {};", name))
+            .collect();
+        items.push(format!("    include {};", process_world));
+        if let Some(exports) = world_exports.get(world) {
+            items.extend(exports.iter().map(|name| format!("    export {};", name)));
+        }
+        let mut world_content = format!(
+            "package {};\n\nworld {} {{\n{}\n}}\n",
+            package,
+            world,
+            items.join("\n")
         );
+        if let Some(world_config) = config.worlds.get(world) {
+            world_content = ensure_world_statements(&world_content, world, &world_config.statements());
+        }
         
-        let world_file = api_dir.join(format!("{}.wit", default_world));
-        println!("Writing default world definition to {}", world_file.display());
-        
-        fs::write(&world_file, world_content)
-            .with_context(|| format!("Failed to write default world file: {}", world_file.display()))?;
-        
-        println!("Successfully created default world definition");
+        let world_file = api_dir.join(format!("{}.wit", world));
+        manifest.files.insert(Manifest::relative(base_dir, &world_file));
+        package_files.insert(world_file.clone(), world_content);
+        to_write.insert(world_file);
+    }
+
+    // Nothing is written unless the whole package resolves
+    println!("Validating the generated WIT package");
+    if let Err(diagnostic) = validate_package(api_dir, &package_files, &new_deps) {
+        return Err(invalid_wit_error(&diagnostic, &generated, &package_files, api_dir));
+    }
+
+    // Generated files edited by hand are only replaced or removed with --force
+    for path in to_write.iter().chain(&orphans) {
+        ensure_unmodified(path, force)?;
     }
+
+    for (path, content) in &new_deps {
+        println!("Writing hyperware:process package to {}", path.display());
+        let dep_dir = path.parent().unwrap_or(api_dir);
+        fs::create_dir_all(dep_dir).with_context(|| format!("Failed to create {}", dep_dir.display()))?;
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+        manifest.directories.insert(Manifest::relative(base_dir, dep_dir));
+    }
+    for path in &to_write {
+        println!("Writing WIT file to {}", path.display());
+        fs::write(path, &package_files[path])
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    for path in &orphans {
+        println!("Removing stale generated file {}", path.display());
+        fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        manifest.files.remove(&Manifest::relative(base_dir, path));
+        manifest.edited_worlds.remove(&Manifest::relative(base_dir, path));
+    }
+    for interface in &generated {
+        manifest.files.insert(Manifest::relative(base_dir, &api_dir.join(interface.file_name())));
+    }
+    manifest.files.retain(|file| base_dir.join(file).exists());
     
     println!("WIT files generated successfully in the 'api' directory.");
     Ok((processed_projects, interfaces))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // WIT gates of the first function in `source`, with the given `[deprecation]` settings
+    fn gates(source: &str, since: Option<&str>, feature: Option<&str>) -> Result<String> {
+        let ast: syn::File = syn::parse_str(source).unwrap();
+        let mut config = Config::default();
+        config.deprecation.since = since.map(str::to_string);
+        config.deprecation.feature = feature.map(str::to_string);
+        let ctx = TypeContext::new(&ast, Path::new("src/lib.rs"), &config);
+        let Some(Item::Fn(item)) = ast.items.first() else { panic!("expected a function") };
+        let gated = deprecation(&item.attrs)?.is_some() || feature_gate(&item.attrs).is_some();
+        stability_wit(&item.attrs, "    ", gated, "function f", item.sig.ident.span(), &ctx)
+    }
+
+    #[test]
+    fn deprecated_items_are_available_since_the_package_version() {
+        let wit = gates(r#"#[deprecated(since = "0.3.0", note = "use g")] fn f() {}"#, Some("0.2.0"), None).unwrap();
+        assert_eq!(
+            wit,
+            "    /// Deprecated: use g\n    @since(version = 0.2.0)\n    @deprecated(version = 0.3.0)\n"
+        );
+        // Without a usable `since` the item is deprecated from the package version on
+        let wit = gates("#[deprecated] fn f() {}", Some("0.2.0"), None).unwrap();
+        assert!(wit.ends_with("    @since(version = 0.2.0)\n    @deprecated(version = 0.2.0)\n"));
+        let wit = gates(r#"#[deprecated(since = "soon")] fn f() {}"#, Some("0.2.0"), None).unwrap();
+        assert!(wit.ends_with("@deprecated(version = 0.2.0)\n"));
+        assert!(gates("#[deprecated] fn f() {}", None, None).is_err());
+        assert_eq!(gates("fn f() {}", None, None).unwrap(), "");
+    }
+
+    #[test]
+    fn deprecated_is_never_older_than_since() {
+        let wit = gates(r#"#[deprecated(since = "0.1.5")] fn f() {}"#, Some("0.2.0"), None).unwrap();
+        assert!(wit.ends_with("    @since(version = 0.2.0)\n    @deprecated(version = 0.2.0)\n"));
+        // Pre-release and build metadata don't make a version older
+        let wit = gates(r#"#[deprecated(since = "0.2.0-rc.1")] fn f() {}"#, Some("0.2.0"), None).unwrap();
+        assert!(wit.ends_with("@deprecated(version = 0.2.0-rc.1)\n"));
+        assert!(version_core("0.10.0") > version_core("0.9.1"));
+    }
+
+    #[test]
+    fn unstable_items_keep_the_rust_since() {
+        let wit = gates(r#"#[deprecated(since = "0.1.0")] fn f() {}"#, Some("0.2.0"), Some("legacy")).unwrap();
+        assert!(wit.ends_with("    @unstable(feature = legacy)\n    @deprecated(version = 0.1.0)\n"));
+        // Handlers kept behind a feature by `--feature-gates`
+        let wit = gates(r#"#[hyper_bindgen_feature = "admin"] fn f() {}"#, None, None).unwrap();
+        assert_eq!(wit, "    @unstable(feature = admin)\n");
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+use walkdir::WalkDir;
+
+use crate::config::{Config, CONFIG_FILE_NAME};
+use crate::naming::{check_wit_identifier, unescape, wit_identifier};
+use crate::world_file::strip_comments;
+
+// The hyperware:process package defining the `standard` interface and the `process-v1` world
+const HYPERWARE_WIT: &str = include_str!("../hyperware.wit");
+
+// Directory under `api/deps/` holding the hyperware:process package
+pub const HYPERWARE_DEP_DIR: &str = "hyperware-process";
+
+// Namespace of the generated package when neither the configuration nor existing WIT names one
+const DEFAULT_NAMESPACE: &str = "local";
+
+// A WIT package name like `namespace:name@1.0.0`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageId {
+    pub namespace: String,
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl PackageId {
+    fn parse(id: &str) -> Option<Self> {
+        let (path, version) = match id.split_once('@') {
+            Some((path, version)) => (path, Some(version.to_string())),
+            None => (id, None),
+        };
+        let (namespace, name) = path.split_once(':')?;
+        Some(PackageId { namespace: namespace.to_string(), name: name.to_string(), version })
+    }
+
+    // Fully qualified reference to an interface or world of this package
+    pub fn item(&self, item: &str) -> String {
+        match &self.version {
+            Some(version) => format!("{}:{}/{}@{}", self.namespace, self.name, item, version),
+            None => format!("{}:{}/{}", self.namespace, self.name, item),
+        }
+    }
+}
+
+impl fmt::Display for PackageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, "@{}", version)?;
+        }
+        Ok(())
+    }
+}
+
+// The `package ...;` declaration of a WIT file, if it has one
+pub fn package_decl(content: &str) -> Option<PackageId> {
+    let mut in_block_comment = false;
+    for line in content.lines() {
+        let code = strip_comments(line, &mut in_block_comment);
+        let code = code.trim();
+        if code.is_empty() {
+            continue;
+        }
+        return PackageId::parse(code.strip_prefix("package ")?.strip_suffix(';')?.trim());
+    }
+    None
+}
+
+// Declare `package` at the top of a WIT file that has no declaration. A declaration of the same
+// package is kept as written. A different one is replaced in files hyper-bindgen created, and is an
+// error in files written by hand.
+pub fn set_package_decl(content: &str, package: &PackageId, path: &Path, generated: bool) -> Result<String> {
+    let decl = format!("package {};", package);
+    match package_decl(content) {
+        Some(existing) if existing == *package => Ok(content.to_string()),
+        Some(existing) if !generated => anyhow::bail!(
+            "Error: {} declares package {}, but the generated WIT is declared in package {}; \
+             change its `package` line or [package] in {} so they match",
+            path.display(),
+            existing,
+            package,
+            CONFIG_FILE_NAME
+        ),
+        Some(existing) => {
+            println!("  Replacing package {} with {}", existing, package);
+            let mut lines: Vec<&str> = content.lines().collect();
+            if let Some(line) = lines.iter().position(|l| l.trim_start().starts_with("package ")) {
+                lines[line] = &decl;
+            }
+            let mut updated = lines.join("\n");
+            if content.ends_with('\n') {
+                updated.push('\n');
+            }
+            Ok(updated)
+        }
+        None => Ok(format!("{}\n\n{}", decl, content)),
+    }
+}
+
+// Version of a crate from its Cargo.toml, following `version.workspace = true`
+fn crate_version(project_path: &Path, cargo_toml: &Value) -> Option<String> {
+    let version = cargo_toml.get("package")?.get("version")?;
+    if let Some(version) = version.as_str() {
+        return Some(version.to_string());
+    }
+    let workspace_toml: Value = fs::read_to_string(project_path.parent()?.join("Cargo.toml"))
+        .ok()?
+        .parse()
+        .ok()?;
+    let version = workspace_toml.get("workspace")?.get("package")?.get("version")?;
+    version.as_str().map(str::to_string)
+}
+
+// Work out the package the generated files are declared in. Each part comes from the
+// configuration, then from a package already declared in `api/`, then from the Cargo metadata
+// of the first process.
+pub fn resolve_package(config: &Config, api_dir: &Path, projects: &[PathBuf]) -> Result<PackageId> {
+    let mut existing = None;
+    for entry in WalkDir::new(api_dir)
+        .max_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+    {
+        let path = entry.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "wit") {
+            if let Some(package) = fs::read_to_string(path).ok().and_then(|c| package_decl(&c)) {
+                println!("Found package {} declared in {}", package, path.display());
+                existing = Some(package);
+                break;
+            }
+        }
+    }
+
+    let mut crate_name = None;
+    let mut crate_version_found = None;
+    if let Some(project_path) = projects.first() {
+        let cargo_toml: Option<Value> = fs::read_to_string(project_path.join("Cargo.toml"))
+            .ok()
+            .and_then(|c| c.parse().ok());
+        if let Some(cargo_toml) = cargo_toml {
+            crate_name = cargo_toml
+                .get("package")
+                .and_then(|p| p.get("name"))
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            crate_version_found = crate_version(project_path, &cargo_toml);
+        }
+    }
+
+    let namespace = match (&config.package.namespace, &existing) {
+        (Some(namespace), _) => namespace.clone(),
+        (None, Some(existing)) => existing.namespace.clone(),
+        (None, None) => DEFAULT_NAMESPACE.to_string(),
+    };
+    let name = match (&config.package.name, &existing, crate_name) {
+        (Some(name), _, _) => name.clone(),
+        (None, Some(existing), _) => existing.name.clone(),
+        (None, None, Some(crate_name)) => {
+            unescape(&wit_identifier(&crate_name, "Crate", &config.naming)?).to_string()
+        }
+        (None, None, None) => anyhow::bail!(
+            "Error: no package name for the generated WIT; set `name` under [package] in {}",
+            CONFIG_FILE_NAME
+        ),
+    };
+    // A package declared without a version stays without one
+    let version = match (&config.package.version, existing) {
+        (Some(version), _) => Some(version.clone()),
+        (None, Some(existing)) => existing.version,
+        (None, None) => crate_version_found,
+    };
+
+    for (part, value) in [("namespace", &namespace), ("name", &name)] {
+        if let Err(reason) = check_wit_identifier(value) {
+            anyhow::bail!(
+                "Error: package {} '{}' is not a valid WIT identifier: {}; set it under [package] in {}",
+                part, value, reason, CONFIG_FILE_NAME
+            );
+        }
+    }
+
+    let package = PackageId { namespace, name, version };
+    println!("Generated WIT is declared in package {}", package);
+    Ok(package)
+}
+
+// The hyperware:process package in `api/deps/`: its name, and the file to write and its content
+// if it's missing. Nothing is written here, so a run that fails leaves `api/` untouched. An
+// existing copy is kept, so it can be updated independently of hyper-bindgen.
+pub fn hyperware_dep(api_dir: &Path) -> Result<(PackageId, Option<(PathBuf, String)>)> {
+    let dep_file = api_dir.join("deps").join(HYPERWARE_DEP_DIR).join("hyperware.wit");
+    if !dep_file.exists() {
+        let package = package_decl(HYPERWARE_WIT).context("the bundled hyperware.wit has no package declaration")?;
+        return Ok((package, Some((dep_file, HYPERWARE_WIT.to_string()))));
+    }
+
+    let content = fs::read_to_string(&dep_file)
+        .with_context(|| format!("Failed to read {}", dep_file.display()))?;
+    let package = package_decl(&content)
+        .with_context(|| format!("{} has no package declaration", dep_file.display()))?;
+    Ok((package, None))
+}
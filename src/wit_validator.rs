@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use wit_parser::{Resolve, SourceMap};
+
+// Resolve the package formed by `files` (path and content of each of its WIT files) against
+// the packages in `api/deps` and the dependency files in `new_deps` that aren't written yet,
+// returning wit-parser's diagnostic if it doesn't resolve
+pub fn validate_package(
+    api_dir: &Path,
+    files: &BTreeMap<PathBuf, String>,
+    new_deps: &BTreeMap<PathBuf, String>,
+) -> Result<(), String> {
+    // Users of the package enable no features by default, so stable items must not rely on
+    // anything behind an `@unstable` gate. Items behind the gates are then checked on their own.
+    resolve_package(api_dir, files, new_deps, false)?;
+    resolve_package(api_dir, files, new_deps, true)
+}
+
+fn resolve_package(
+    api_dir: &Path,
+    files: &BTreeMap<PathBuf, String>,
+    new_deps: &BTreeMap<PathBuf, String>,
+    all_features: bool,
+) -> Result<(), String> {
+    let mut resolve = Resolve {
+        all_features,
+        ..Default::default()
+    };
+
+    // Dependency directories, with the content of the ones that only exist in `new_deps`
+    let deps_dir = api_dir.join("deps");
+    let mut deps: BTreeMap<PathBuf, Option<(&PathBuf, &String)>> = BTreeMap::new();
+    for entry in WalkDir::new(&deps_dir)
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+    {
+        deps.insert(entry.path().to_path_buf(), None);
+    }
+    for (path, content) in new_deps {
+        if let Some(dir) = path.parent() {
+            deps.insert(dir.to_path_buf(), Some((path, content)));
+        }
+    }
+
+    // Dependencies are pushed in name order, so one may only depend on packages sorting before it
+    for (dir, new) in deps {
+        println!("Loading WIT dependency {}", dir.display());
+        let loaded = match new {
+            Some((path, content)) => {
+                let mut sources = SourceMap::new();
+                sources.push(path, content.as_str());
+                sources.parse().and_then(|package| resolve.push_group(package)).map(|_| ())
+            }
+            None => resolve.push_path(&dir).map(|_| ()),
+        };
+        loaded.map_err(|e| format!("failed to load dependency {}: {:#}", dir.display(), e))?;
+    }
+
+    let mut sources = SourceMap::new();
+    for (path, content) in files {
+        sources.push(path, content.as_str());
+    }
+    let package = sources.parse().map_err(|e| format!("{:#}", e))?;
+    resolve.push_group(package).map_err(|e| format!("{:#}", e))?;
+    Ok(())
+}
+
+// File and 1-based line of a wit-parser diagnostic, from the `--> file:line:column` it renders
+pub fn diagnostic_location(diagnostic: &str) -> Option<(PathBuf, usize)> {
+    let location = diagnostic.lines().find_map(|l| l.trim().strip_prefix("--> "))?;
+    let mut parts = location.rsplitn(3, ':');
+    let _column = parts.next()?;
+    let line = parts.next()?.parse().ok()?;
+    Some((PathBuf::from(parts.next()?), line))
+}
+
+// Name of the type or record definition enclosing a 1-based line of a WIT file
+pub fn enclosing_item(content: &str, line: usize) -> Option<String> {
+    content
+        .lines()
+        .take(line)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .find_map(|l| {
+            let mut tokens = l.split_whitespace();
+            match tokens.next()? {
+                "record" | "variant" | "enum" | "flags" | "type" | "resource" => {
+                    Some(tokens.next()?.trim_end_matches('{').to_string())
+                }
+                _ => None,
+            }
+        })
+}
@@ -0,0 +1,357 @@
+use std::collections::HashSet;
+
+// A `world name { ... }` definition found in a WIT file
+pub struct WorldDef {
+    pub name: String,
+    // Lines of the opening `world name {` and of the matching closing brace
+    start: usize,
+    end: usize,
+    // Plain `import name;` items of the world: (line, interface name as written)
+    pub imports: Vec<(usize, String)>,
+    // Plain `export name;` items of the world: (line, interface name as written)
+    pub exports: Vec<(usize, String)>,
+    // `include name;` items of the world: (line, world name as written)
+    pub includes: Vec<(usize, String)>,
+    // Every single-line item of the world with its whitespace normalized: (line, statement)
+    statements: Vec<(usize, String)>,
+}
+
+// Remove `//` and `/* */` comments from a line, tracking whether a block comment is still open
+pub fn strip_comments(line: &str, in_block_comment: &mut bool) -> String {
+    let mut code = String::new();
+    let mut rest = line;
+    loop {
+        if *in_block_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    rest = &rest[end + 2..];
+                    *in_block_comment = false;
+                }
+                None => return code,
+            }
+        }
+        match (rest.find("//"), rest.find("/*")) {
+            (Some(line_start), block_start) if block_start.is_none_or(|b| line_start < b) => {
+                code.push_str(&rest[..line_start]);
+                return code;
+            }
+            (_, Some(block_start)) => {
+                code.push_str(&rest[..block_start]);
+                rest = &rest[block_start + 2..];
+                *in_block_comment = true;
+            }
+            _ => {
+                code.push_str(rest);
+                return code;
+            }
+        }
+    }
+}
+
+// Interface name of a plain `import name;` or `export name;` item, skipping items of external
+// packages (`import ns:pkg/iface;`) and inline items (`import name: func(...);`)
+fn plain_item(code: &str, keyword: &str) -> Option<String> {
+    let name = code.trim().strip_prefix(keyword)?.strip_prefix(' ')?.strip_suffix(';')?.trim();
+    let is_identifier = !name.is_empty()
+        && name.trim_start_matches('%').chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    is_identifier.then(|| name.to_string())
+}
+
+// A world item with its whitespace normalized, e.g. `import  a ;` -> `import a;`
+fn normalize_statement(code: &str) -> String {
+    code.split_whitespace().collect::<Vec<_>>().join(" ").replace(" ;", ";")
+}
+
+// World name of an `include name;` item
+fn include(code: &str) -> Option<String> {
+    let name = code.trim().strip_prefix("include ")?.strip_suffix(';')?.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// Find the top-level world definitions of a WIT file, ignoring anything inside comments
+pub fn parse_worlds(content: &str) -> Vec<WorldDef> {
+    let mut worlds = Vec::new();
+    let mut current: Option<WorldDef> = None;
+    let mut depth = 0usize;
+    let mut in_block_comment = false;
+
+    for (index, line) in content.lines().enumerate() {
+        let code = strip_comments(line, &mut in_block_comment);
+        let tokens: Vec<&str> = code.split_whitespace().collect();
+
+        if depth == 0 && tokens.first() == Some(&"world") {
+            if let Some(name) = tokens.get(1) {
+                let name = name.trim_end_matches('{').trim_end_matches("{}");
+                current = Some(WorldDef {
+                    name: name.to_string(),
+                    start: index,
+                    end: index,
+                    imports: Vec::new(),
+                    exports: Vec::new(),
+                    includes: Vec::new(),
+                    statements: Vec::new(),
+                });
+            }
+        } else if depth == 1 {
+            if let Some(world) = current.as_mut() {
+                let statement = normalize_statement(&code);
+                if !statement.is_empty() && !statement.starts_with('}') {
+                    world.statements.push((index, statement));
+                }
+                if let Some(import) = plain_item(&code, "import") {
+                    world.imports.push((index, import));
+                } else if let Some(export) = plain_item(&code, "export") {
+                    world.exports.push((index, export));
+                } else if let Some(included) = include(&code) {
+                    world.includes.push((index, included));
+                }
+            }
+        }
+
+        for c in code.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        if depth == 0 {
+            if let Some(mut world) = current.take() {
+                world.end = index;
+                worlds.push(world);
+            }
+        }
+    }
+
+    worlds
+}
+
+// Add the `wanted` imports (or exports, by `keyword`) to the named world and drop the ones
+// hyper-bindgen owns that are no longer wanted. Everything else in the file is left as written.
+// Returns None if the world isn't defined in the file or is written on a single line with
+// content we can't edit.
+pub fn update_world_items(
+    content: &str,
+    world_name: &str,
+    keyword: &str,
+    wanted: &[String],
+    owned: &HashSet<String>,
+) -> Option<String> {
+    let world = parse_worlds(content).into_iter().find(|w| w.name == world_name)?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+    // `world name {}` is expanded so imports can be added
+    if world.start == world.end && !wanted.is_empty() {
+        let line = &lines[world.start];
+        let open = line.find('{')?;
+        if !line[open + 1..].trim().starts_with('}') {
+            println!("World {} is defined on a single line, leaving it unchanged", world_name);
+            return None;
+        }
+        let (head, tail) = (line[..=open].to_string(), line[open + 1..].trim().to_string());
+        lines.splice(world.start..=world.start, [head, tail]);
+        let mut expanded = lines.join("\n");
+        if content.ends_with('\n') {
+            expanded.push('\n');
+        }
+        return update_world_items(&expanded, world_name, keyword, wanted, owned);
+    }
+
+    // Items of other packages count as present too, so configured ones aren't added twice
+    let present: HashSet<&str> = world
+        .statements
+        .iter()
+        .filter_map(|(_, s)| s.strip_prefix(keyword)?.strip_prefix(' ')?.strip_suffix(';'))
+        .collect();
+    let items = if keyword == "export" { &world.exports } else { &world.imports };
+    let indent = world
+        .statements
+        .first()
+        .map(|(line, _)| lines[*line][..lines[*line].len() - lines[*line].trim_start().len()].to_string())
+        .unwrap_or_else(|| "    ".to_string());
+
+    // New items go after the last of their kind. Imports otherwise go at the top of the body
+    // and exports at the bottom.
+    let insert_at = match (items.last(), world.statements.last()) {
+        (Some((line, _)), _) => line + 1,
+        (None, Some((line, _))) if keyword == "export" => line + 1,
+        _ => world.start + 1,
+    };
+    let missing: Vec<String> = wanted
+        .iter()
+        .filter(|name| !present.contains(name.as_str()))
+        .map(|name| {
+            println!("  Adding {} {} to world {}", keyword, name, world_name);
+            format!("{}{} {};", indent, keyword, name)
+        })
+        .collect();
+
+    // Stale items are removed from the bottom up so earlier line numbers stay valid
+    let mut stale: Vec<usize> = items
+        .iter()
+        .filter(|(_, name)| owned.contains(name) && !wanted.contains(name))
+        .map(|(line, name)| {
+            println!("  Removing {} {} from world {}", keyword, name, world_name);
+            *line
+        })
+        .collect();
+    lines.splice(insert_at..insert_at, missing);
+    stale.sort_unstable_by(|a, b| b.cmp(a));
+    for line in stale {
+        lines.remove(line);
+    }
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') {
+        updated.push('\n');
+    }
+    Some(updated)
+}
+
+// Replace `include from;` in the named world with `include to;`
+pub fn replace_include(content: &str, world_name: &str, from: &str, to: &str) -> String {
+    let Some(world) = parse_worlds(content).into_iter().find(|w| w.name == world_name) else {
+        return content.to_string();
+    };
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    for (line, included) in &world.includes {
+        if included == from {
+            println!("  Replacing include {} with {} in world {}", from, to, world_name);
+            lines[*line] = lines[*line].replacen(from, to, 1);
+        }
+    }
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated
+}
+
+// Add the `statements` (e.g. `include wasi:cli/imports@0.2.0;`) the named world doesn't have
+// yet, each after the last item of the same kind
+pub fn ensure_world_statements(content: &str, world_name: &str, statements: &[String]) -> String {
+    let Some(world) = parse_worlds(content).into_iter().find(|w| w.name == world_name) else {
+        return content.to_string();
+    };
+    if world.start == world.end {
+        println!("World {} is defined on a single line, leaving it unchanged", world_name);
+        return content.to_string();
+    }
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut items = world.statements.clone();
+
+    for statement in statements {
+        let statement = normalize_statement(statement);
+        if items.iter().any(|(_, existing)| *existing == statement) {
+            continue;
+        }
+        println!("  Adding `{}` to world {}", statement, world_name);
+
+        let keyword = statement.split_whitespace().next().unwrap_or_default();
+        let insert_at = items
+            .iter()
+            .rev()
+            .find(|(_, existing)| existing.split_whitespace().next() == Some(keyword))
+            .or(items.last())
+            .map(|(line, _)| line + 1)
+            .unwrap_or(world.start + 1);
+        lines.insert(insert_at, format!("    {}", statement));
+
+        // Later items move down by the inserted line
+        for (line, _) in items.iter_mut() {
+            if *line >= insert_at {
+                *line += 1;
+            }
+        }
+        items.push((insert_at, statement));
+        items.sort();
+    }
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD: &str = "package my:app;
+
+// world commented-out { import gone; }
+world app-v0 {
+    import wasi:http/outgoing-handler@0.2.0;
+    import old-api; // generated earlier
+    import hand-written;
+    include process-v1;
+    export app;
+}
+";
+
+    fn owned(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn worlds_in_comments_are_ignored() {
+        let worlds = parse_worlds(WORLD);
+        assert_eq!(worlds.len(), 1);
+        assert_eq!(worlds[0].name, "app-v0");
+        let imports: Vec<&str> = worlds[0].imports.iter().map(|(_, name)| name.as_str()).collect();
+        assert_eq!(imports, ["old-api", "hand-written"]);
+        assert_eq!(worlds[0].includes[0].1, "process-v1");
+    }
+
+    #[test]
+    fn updating_imports_keeps_items_hyper_bindgen_doesnt_own() {
+        let wanted = vec!["new-api".to_string()];
+        let updated = update_world_items(WORLD, "app-v0", "import", &wanted, &owned(&["old-api", "new-api"])).unwrap();
+        assert_eq!(
+            updated,
+            "package my:app;
+
+// world commented-out { import gone; }
+world app-v0 {
+    import wasi:http/outgoing-handler@0.2.0;
+    import hand-written;
+    import new-api;
+    include process-v1;
+    export app;
+}
+"
+        );
+        // Nothing changes once the world has what is wanted
+        let again = update_world_items(&updated, "app-v0", "import", &wanted, &owned(&["old-api", "new-api"])).unwrap();
+        assert_eq!(again, updated);
+    }
+
+    #[test]
+    fn exports_go_after_the_last_export() {
+        let wanted = vec!["app".to_string(), "admin".to_string()];
+        let updated = update_world_items(WORLD, "app-v0", "export", &wanted, &owned(&["app", "admin"])).unwrap();
+        assert!(updated.contains("    export app;\n    export admin;\n}"));
+        assert!(updated.contains("    import old-api; // generated earlier\n"));
+    }
+
+    #[test]
+    fn empty_worlds_are_expanded() {
+        let updated = update_world_items("world w {}\n", "w", "import", &["api".to_string()], &owned(&[])).unwrap();
+        assert_eq!(updated, "world w {\n    import api;\n}\n");
+        assert!(update_world_items("world w { import a; }\n", "w", "import", &["api".to_string()], &owned(&[])).is_none());
+        assert!(update_world_items(WORLD, "other", "import", &[], &owned(&[])).is_none());
+    }
+
+    #[test]
+    fn includes_are_replaced_and_statements_added_once() {
+        let replaced = replace_include(WORLD, "app-v0", "process-v1", "hyperware:process/process-v1@1.0.0");
+        assert!(replaced.contains("    include hyperware:process/process-v1@1.0.0;\n"));
+        let statements = vec!["include  wasi:cli/imports@0.2.0 ;".to_string(), "export app;".to_string()];
+        let updated = ensure_world_statements(WORLD, "app-v0", &statements);
+        assert!(updated.contains("    include process-v1;\n    include wasi:cli/imports@0.2.0;\n    export app;\n}"));
+        assert_eq!(ensure_world_statements(&updated, "app-v0", &statements), updated);
+    }
+}
@@ -0,0 +1,567 @@
+// End-to-end checks running the hyper-bindgen binary on small workspaces
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const GOOD_LIB_RS: &str = r#"
+#[derive(Serialize, Deserialize)]
+pub struct Item {
+    id: u64,
+}
+
+#[derive(Default)]
+pub struct AlphaState {}
+
+#[hyperprocess(wit_world = "app-v0")]
+impl AlphaState {
+    #[remote]
+    fn get_item(&self, id: u64) -> Item {
+        todo!()
+    }
+}
+"#;
+
+// An untagged enum has no WIT representation, so the project can't be converted
+const BAD_LIB_RS: &str = r#"
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Item {
+    Id(u64),
+    Name(String),
+}
+
+#[derive(Default)]
+pub struct AlphaState {}
+
+#[hyperprocess(wit_world = "app-v0")]
+impl AlphaState {
+    #[remote]
+    fn get_item(&self, id: u64) -> Item {
+        todo!()
+    }
+}
+"#;
+
+// A workspace with a single process crate `alpha`
+fn workspace(name: &str, lib_rs: &str) -> PathBuf {
+    workspace_of(name, &[("alpha", lib_rs)])
+}
+
+// A workspace with a process crate for each name and lib.rs
+fn workspace_of(name: &str, processes: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hyper-bindgen-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let members: Vec<String> = processes.iter().map(|(process, _)| format!("\"{}\"", process)).collect();
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Cargo.toml"), format!("[workspace]\nmembers = [{}]\n", members.join(", "))).unwrap();
+    for (process, lib_rs) in processes {
+        fs::create_dir_all(dir.join(process).join("src")).unwrap();
+        fs::write(
+            dir.join(process).join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+                 [package.metadata.component]\npackage = \"hyperware:process\"\n",
+                process
+            ),
+        )
+        .unwrap();
+        fs::write(dir.join(process).join("src").join("lib.rs"), lib_rs).unwrap();
+    }
+    dir
+}
+
+// Run hyper-bindgen in `dir`, returning whether it succeeded
+fn run(dir: &Path) -> bool {
+    run_with(dir, &[])
+}
+
+// Run hyper-bindgen in `dir` with the given arguments, returning whether it succeeded
+fn run_with(dir: &Path, args: &[&str]) -> bool {
+    let output = Command::new(env!("CARGO_BIN_EXE_hyper-bindgen"))
+        .args(args)
+        .current_dir(dir)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    output.status.success()
+}
+
+#[test]
+fn project_that_cant_be_converted_fails_the_run() {
+    let dir = workspace("bad-project", BAD_LIB_RS);
+    assert!(!run(&dir));
+    assert!(!dir.join("api").join("alpha.wit").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failing_project_keeps_its_generated_files() {
+    let dir = workspace("keeps-files", GOOD_LIB_RS);
+    assert!(run(&dir));
+    let interface = fs::read_to_string(dir.join("api").join("alpha.wit")).unwrap();
+    let world = fs::read_to_string(dir.join("api").join("app-v0.wit")).unwrap();
+
+    fs::write(dir.join("alpha").join("src").join("lib.rs"), BAD_LIB_RS).unwrap();
+    assert!(!run(&dir));
+    assert_eq!(fs::read_to_string(dir.join("api").join("alpha.wit")).unwrap(), interface);
+    assert_eq!(fs::read_to_string(dir.join("api").join("app-v0.wit")).unwrap(), world);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn project_without_lib_rs_keeps_its_generated_files() {
+    let dir = workspace("missing-lib-rs", GOOD_LIB_RS);
+    assert!(run(&dir));
+    let interface = fs::read_to_string(dir.join("api").join("alpha.wit")).unwrap();
+
+    fs::remove_file(dir.join("alpha").join("src").join("lib.rs")).unwrap();
+    assert!(!run(&dir));
+    assert_eq!(fs::read_to_string(dir.join("api").join("alpha.wit")).unwrap(), interface);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn package_that_doesnt_resolve_writes_nothing() {
+    let dir = workspace("unresolved", GOOD_LIB_RS);
+    let api_dir = dir.join("api");
+    fs::create_dir_all(&api_dir).unwrap();
+    let world = "package local:alpha@0.1.0;\n\nworld app-v0 {\n    import missing:pkg/thing;\n}\n";
+    fs::write(api_dir.join("app-v0.wit"), world).unwrap();
+
+    assert!(!run(&dir));
+    assert_eq!(fs::read_to_string(api_dir.join("app-v0.wit")).unwrap(), world);
+    assert!(!api_dir.join("alpha.wit").exists());
+    assert!(!api_dir.join("deps").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+const BETA_LIB_RS: &str = r#"
+use caller_utils::alpha::get_item_remote_rpc;
+
+#[derive(Default)]
+pub struct BetaState {}
+
+#[hyperprocess(wit_world = "beta-v0")]
+impl BetaState {
+    // Not a call: caller_utils::gamma::noop()
+    #[remote]
+    fn describe(&self, id: u64) -> String {
+        let _ = get_item_remote_rpc(id);
+        "caller_utils::gamma".to_string()
+    }
+}
+"#;
+
+const GAMMA_LIB_RS: &str = r#"
+#[derive(Default)]
+pub struct GammaState {}
+
+#[hyperprocess(wit_world = "gamma-v0")]
+impl GammaState {
+    #[remote]
+    fn noop(&self) -> bool {
+        true
+    }
+}
+"#;
+
+#[test]
+fn each_process_gets_a_world_importing_what_it_calls() {
+    let dir = workspace_of(
+        "worlds",
+        &[("alpha", GOOD_LIB_RS), ("beta", BETA_LIB_RS), ("gamma", GAMMA_LIB_RS)],
+    );
+    assert!(run(&dir));
+    let alpha = fs::read_to_string(dir.join("api").join("app-v0.wit")).unwrap();
+    let beta = fs::read_to_string(dir.join("api").join("beta-v0.wit")).unwrap();
+    assert!(alpha.contains("export alpha;"));
+    assert!(!alpha.contains("import "));
+    assert!(beta.contains("export beta;"));
+    assert!(beta.contains("import alpha;"));
+    assert!(!beta.contains("gamma"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn processes_sharing_a_world_share_its_exports_and_imports() {
+    let dir = workspace_of(
+        "shared-world",
+        &[
+            ("alpha", GOOD_LIB_RS),
+            ("beta", &BETA_LIB_RS.replace("beta-v0", "app-v0")),
+            ("gamma", GAMMA_LIB_RS),
+        ],
+    );
+    assert!(run(&dir));
+    let world = fs::read_to_string(dir.join("api").join("app-v0.wit")).unwrap();
+    assert!(world.contains("export alpha;"));
+    assert!(world.contains("export beta;"));
+    // beta calls alpha, which the world already exports
+    assert!(!world.contains("import alpha;"));
+    assert!(!world.contains("gamma"));
+    assert!(!dir.join("api").join("beta-v0.wit").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn clean_restores_edited_worlds() {
+    let dir = workspace("clean", GOOD_LIB_RS);
+    let api_dir = dir.join("api");
+    fs::create_dir_all(&api_dir).unwrap();
+    let world = "// Hand-written world\nworld app-v0 {\n    include process-v1;\n}\n";
+    fs::write(api_dir.join("app-v0.wit"), world).unwrap();
+
+    assert!(run(&dir));
+    let edited = fs::read_to_string(api_dir.join("app-v0.wit")).unwrap();
+    assert!(edited.contains("export alpha;"));
+    assert!(edited.contains("include hyperware:process/process-v1"));
+
+    let status = Command::new(env!("CARGO_BIN_EXE_hyper-bindgen"))
+        .arg("clean")
+        .current_dir(&dir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(api_dir.join("app-v0.wit")).unwrap(), world);
+    assert!(!api_dir.join("alpha.wit").exists());
+    assert!(!api_dir.join("deps").join("hyperware-process").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A process whose handler returns an `Error` type defined by `fields`
+fn error_lib_rs(process: &str, fields: &str) -> String {
+    format!(
+        r#"
+#[derive(Serialize, Deserialize)]
+pub struct Error {{
+    {fields}
+}}
+
+#[derive(Default)]
+pub struct {state}State {{}}
+
+#[hyperprocess(wit_world = "{process}-v0")]
+impl {state}State {{
+    #[remote]
+    fn check(&self) -> Error {{
+        todo!()
+    }}
+}}
+"#,
+        fields = fields,
+        state = process[..1].to_uppercase() + &process[1..],
+        process = process
+    )
+}
+
+#[test]
+fn differently_defined_types_with_the_same_name_fail_the_run() {
+    let dir = workspace_of(
+        "type-clash",
+        &[
+            ("alpha", &error_lib_rs("alpha", "code: u64,")),
+            ("beta", &error_lib_rs("beta", "message: String,")),
+        ],
+    );
+    assert!(!run(&dir));
+    assert!(!dir.join("api").join("alpha.wit").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn identically_defined_types_are_shared() {
+    let dir = workspace_of(
+        "shared-types",
+        &[
+            ("alpha", &error_lib_rs("alpha", "code: u64,")),
+            ("beta", &error_lib_rs("beta", "code: u64,")),
+        ],
+    );
+    assert!(run(&dir));
+    let types = fs::read_to_string(dir.join("api").join("types.wit")).unwrap();
+    assert!(types.contains("record error {"));
+    let alpha = fs::read_to_string(dir.join("api").join("alpha.wit")).unwrap();
+    assert!(alpha.contains("use types.{error};"));
+    let caller_utils = fs::read_to_string(dir.join("caller-utils").join("src").join("lib.rs")).unwrap();
+    assert_eq!(caller_utils.matches("pub use crate::wit_custom::Error;").count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn own_types_named_like_a_mapping_keep_their_definition() {
+    let dir = workspace(
+        "own-duration",
+        &GOOD_LIB_RS.replace("pub struct Item", "pub struct Duration").replace("-> Item", "-> Duration"),
+    );
+    assert!(run(&dir));
+    let caller_utils = fs::read_to_string(dir.join("caller-utils").join("src").join("lib.rs")).unwrap();
+    assert!(caller_utils.contains("pub use crate::wit_custom::Duration;"));
+    assert!(!caller_utils.contains("std::time::Duration"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+const TREE_LIB_RS: &str = r#"
+#[derive(Serialize, Deserialize)]
+pub struct Node {
+    name: String,
+    children: Vec<Node>,
+}
+
+#[derive(Default)]
+pub struct AlphaState {}
+
+#[hyperprocess(wit_world = "app-v0")]
+impl AlphaState {
+    #[remote]
+    fn tree(&self) -> Node {
+        todo!()
+    }
+}
+"#;
+
+#[test]
+fn recursive_types_fail_without_a_fallback() {
+    let dir = workspace("recursive", TREE_LIB_RS);
+    assert!(!run(&dir));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recursive_fallback_keeps_the_json_of_the_field_for_callers() {
+    let dir = workspace("recursive-fallback", TREE_LIB_RS);
+    fs::write(dir.join("hyper-bindgen.toml"), "[types]\nrecursive-fallback = \"string\"\n").unwrap();
+    assert!(run(&dir));
+    let wit = fs::read_to_string(dir.join("api").join("alpha.wit")).unwrap();
+    assert!(wit.contains("// Serde: with = \"crate::json_string\"\n        children: string"));
+    let caller_utils = fs::read_to_string(dir.join("caller-utils").join("src").join("lib.rs")).unwrap();
+    assert!(caller_utils.contains("mod json_string {"));
+    assert!(caller_utils.contains("    #[serde(with = \"crate::json_string\")]\n    pub children: String,"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recursive_fallback_other_than_string_is_rejected() {
+    let dir = workspace("recursive-fallback-list", TREE_LIB_RS);
+    fs::write(dir.join("hyper-bindgen.toml"), "[types]\nrecursive-fallback = \"list<u8>\"\n").unwrap();
+    assert!(!run(&dir));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+const ADMIN_LIB_RS: &str = r#"
+#[derive(Default)]
+pub struct AlphaState {}
+
+#[hyperprocess(wit_world = "app-v0")]
+impl AlphaState {
+    #[remote]
+    fn ping(&self) -> u64 {
+        todo!()
+    }
+
+    #[cfg(feature = "admin")]
+    #[remote]
+    fn reset(&self) -> u64 {
+        todo!()
+    }
+}
+"#;
+
+#[test]
+fn cfg_flags_are_accepted_after_the_subcommand() {
+    let dir = workspace("cfg-flags", ADMIN_LIB_RS);
+    assert!(run_with(&dir, &["generate", "--features", "admin", "--cfg", "test"]));
+    let wit = fs::read_to_string(dir.join("api").join("alpha.wit")).unwrap();
+    assert!(wit.contains("reset"));
+    assert!(run_with(&dir, &["generate", "--no-default-features"]));
+    let wit = fs::read_to_string(dir.join("api").join("alpha.wit")).unwrap();
+    assert!(wit.contains("ping") && !wit.contains("reset"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_are_removed_once_nothing_is_generated() {
+    let dir = workspace("nothing-generated", GOOD_LIB_RS);
+    let workspace_cargo_toml = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    assert!(run(&dir));
+    assert!(dir.join("caller-utils").exists());
+
+    // A process without hyperprocess impls generates no interface
+    fs::write(dir.join("alpha").join("src").join("lib.rs"), "pub struct AlphaState {}\n").unwrap();
+    assert!(run(&dir));
+    assert!(!dir.join("api").join("alpha.wit").exists());
+    assert!(!dir.join("api").join("app-v0.wit").exists());
+    assert!(!dir.join("caller-utils").exists());
+    assert_eq!(fs::read_to_string(dir.join("Cargo.toml")).unwrap(), workspace_cargo_toml);
+    assert!(!fs::read_to_string(dir.join("alpha").join("Cargo.toml")).unwrap().contains("caller-utils"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_are_removed_once_no_process_is_left() {
+    let dir = workspace("no-process-left", GOOD_LIB_RS);
+    assert!(run(&dir));
+
+    fs::write(dir.join("alpha").join("Cargo.toml"), "[package]\nname = \"alpha\"\nversion = \"0.1.0\"\n").unwrap();
+    assert!(run(&dir));
+    assert!(!dir.join("api").join("alpha.wit").exists());
+    assert!(!dir.join("api").join("app-v0.wit").exists());
+    assert!(!dir.join("caller-utils").exists());
+    let manifest = fs::read_to_string(dir.join("api").join(".hyper-bindgen-manifest.toml")).unwrap();
+    assert!(manifest.contains("files = []"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+const DOCUMENTED_LIB_RS: &str = r#"
+/// A user of the app
+///
+/// Created on sign-up.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    /// Name shown to others
+    display_name: String,
+    status: Status,
+}
+
+/// Whether a user can sign in
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Signed up and confirmed
+    Active,
+    /// Locked by an admin, with the reason
+    Locked(String),
+}
+
+#[derive(Default)]
+pub struct AlphaState {}
+
+#[hyperprocess(wit_world = "app-v0")]
+impl AlphaState {
+    #[remote]
+    fn get_user(&self) -> User {
+        todo!()
+    }
+}
+"#;
+
+#[test]
+fn locally_defined_types_keep_their_docs() {
+    let dir = workspace("local-docs", DOCUMENTED_LIB_RS);
+    assert!(run(&dir));
+    let caller_utils = fs::read_to_string(dir.join("caller-utils").join("src").join("lib.rs")).unwrap();
+    assert!(caller_utils.contains("/// A user of the app\n///\n/// Created on sign-up.\n#[derive("));
+    assert!(caller_utils.contains("    /// Name shown to others\n    #[serde(rename = \"displayName\")]\n    pub display_name: String,"));
+    assert!(caller_utils.contains("/// Whether a user can sign in\n#[derive("));
+    assert!(caller_utils.contains("    /// Signed up and confirmed\n    #[serde(rename = \"active\")]\n    Active,"));
+    assert!(caller_utils.contains("    /// Locked by an admin, with the reason\n    #[serde(rename = \"locked\")]\n    Locked(String),"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unknown_config_keys_are_rejected() {
+    let dir = workspace("unknown-config-keys", GOOD_LIB_RS);
+    for config in [
+        "[type_mapping]\nwit = \"string\"\n",
+        "[types]\nfeature-gates = true\n",
+        "[types.mappings.\"uuid::Uuid\"]\nwit = \"string\"\ndependencies = \"uuid = 1\"\n",
+        "[worlds.app-v0]\nimport = [\"wasi:http/outgoing-handler@0.2.0\"]\n",
+    ] {
+        fs::write(dir.join("hyper-bindgen.toml"), config).unwrap();
+        assert!(!run(&dir), "accepted {}", config);
+        assert!(!dir.join("api").join("alpha.wit").exists());
+    }
+    fs::write(dir.join("hyper-bindgen.toml"), "[cfg]\nfeature-gates = true\n").unwrap();
+    assert!(run(&dir));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Contents of the files under `dir`, keyed by their path
+fn snapshot(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                files.push((path.clone(), fs::read(&path).unwrap()));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+// Enough types that an unordered map would shuffle them between runs
+const MANY_TYPES_LIB_RS: &str = r#"
+#[derive(Serialize, Deserialize)]
+pub struct Order {
+    id: u64,
+    lines: Vec<Line>,
+    status: Status,
+    customer: Customer,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Line {
+    sku: String,
+    quantity: u32,
+    price: Price,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Price(u64);
+
+#[derive(Serialize, Deserialize)]
+pub struct Customer {
+    name: String,
+    address: Option<Address>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Address {
+    street: String,
+    city: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Status {
+    Open,
+    Shipped(u64),
+    Cancelled { reason: String },
+}
+
+#[derive(Default)]
+pub struct GammaState {}
+
+#[hyperprocess(wit_world = "gamma-v0")]
+impl GammaState {
+    #[remote]
+    fn place(&self, order: Order) -> Status {
+        todo!()
+    }
+
+    #[local]
+    fn lines(&self, id: u64) -> Vec<Line> {
+        todo!()
+    }
+
+    #[http]
+    fn customer(&self, id: u64) -> Customer {
+        todo!()
+    }
+}
+"#;
+
+#[test]
+fn second_run_writes_identical_files() {
+    let dir = workspace_of("identical-runs", &[("alpha", GOOD_LIB_RS), ("gamma", MANY_TYPES_LIB_RS)]);
+    assert!(run(&dir));
+    let first = snapshot(&dir);
+    assert!(run(&dir));
+    assert_eq!(snapshot(&dir), first);
+    fs::remove_dir_all(&dir).unwrap();
+}